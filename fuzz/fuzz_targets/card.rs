@@ -0,0 +1,12 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use zkwasm_application::card::Card;
+use zkwasm_application::storage::TryFromData;
+
+#[path = "words.rs"]
+mod words;
+
+fuzz_target!(|data: &[u8]| {
+    let mut words = words::words(data);
+    let _ = Card::try_from_data(&mut words.iter_mut());
+});
@@ -0,0 +1,15 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use zkwasm_application::state::Transaction;
+
+#[path = "words.rs"]
+mod words;
+
+fuzz_target!(|data: &[u8]| {
+    let params = words::words(data);
+    if let Ok(tx) = Transaction::try_decode(&params) {
+        // decoded transactions must survive an encode and decode round trip
+        let encoded = tx.encode().unwrap();
+        assert_eq!(Transaction::decode(&encoded).encode(), Some(encoded));
+    }
+});
@@ -0,0 +1,21 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use zkwasm_application::player::PlayerData;
+use zkwasm_application::storage::TryFromData;
+use zkwasm_rest_abi::StorageData;
+
+#[path = "words.rs"]
+mod words;
+
+fuzz_target!(|data: &[u8]| {
+    let mut words = words::words(data);
+    if let Ok(player) = PlayerData::try_from_data(&mut words.iter_mut()) {
+        // accepted records are migrated to the current schema and must decode again
+        let mut encoded = vec![];
+        player.to_data(&mut encoded);
+        let player = PlayerData::try_from_data(&mut encoded.clone().iter_mut()).unwrap();
+        let mut reencoded = vec![];
+        player.to_data(&mut reencoded);
+        assert_eq!(encoded, reencoded);
+    }
+});
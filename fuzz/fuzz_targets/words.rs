@@ -0,0 +1,10 @@
+// Reads the fuzzer input as little endian u64 words, the last word is zero padded
+pub fn words(data: &[u8]) -> Vec<u64> {
+    data.chunks(8)
+        .map(|c| {
+            let mut bytes = [0u8; 8];
+            bytes[..c.len()].copy_from_slice(c);
+            u64::from_le_bytes(bytes)
+        })
+        .collect()
+}
@@ -0,0 +1,44 @@
+use crate::config::ADMIN_PUBKEY;
+use crate::error::ERROR_NOT_AUTHORIZED;
+use crate::player::AutomataPlayer;
+use crate::storage;
+
+/* Admin registry
+ *
+ * Every admin has one record at [ADMIN_PREFIX, pid[0], pid[1], ADMIN_POSTFIX] holding a
+ * mask of the roles below. The key baked in from admin.pubkey holds every role until a
+ * record is stored for it, so a compromised build key can be revoked without a rebuild.
+ */
+pub const ROLE_SUPERADMIN: u64 = 1; // adds and removes admins
+pub const ROLE_TICKER: u64 = 1 << 1; // Tick
+pub const ROLE_DEPOSITOR: u64 = 1 << 2; // Deposit, RefundDeposit
+pub const ROLE_CONFIG_MANAGER: u64 = 1 << 3; // SetWithdrawLimits, SetPause, SetConfig
+pub const ROLE_ALL: u64 = ROLE_SUPERADMIN | ROLE_TICKER | ROLE_DEPOSITOR | ROLE_CONFIG_MANAGER;
+
+const ADMIN_PREFIX: u64 = 0xad31;
+const ADMIN_POSTFIX: u64 = 0xad32;
+
+fn key(pid: &[u64; 2]) -> [u64; 4] {
+    [ADMIN_PREFIX, pid[0], pid[1], ADMIN_POSTFIX]
+}
+
+pub fn get_roles(pid: &[u64; 2]) -> u64 {
+    match storage::get(&key(pid)).first() {
+        Some(roles) => *roles,
+        None if *pid == AutomataPlayer::pkey_to_pid(&ADMIN_PUBKEY) => ROLE_ALL,
+        None => 0,
+    }
+}
+
+/// Stores roles for pid, 0 removes every role
+pub fn set_roles(pid: &[u64; 2], roles: u64) {
+    storage::set(&key(pid), &[roles]);
+}
+
+pub fn check_role(pid: &[u64; 2], role: u64) -> Result<(), u32> {
+    if get_roles(pid) & role == role {
+        Ok(())
+    } else {
+        Err(ERROR_NOT_AUTHORIZED)
+    }
+}
@@ -0,0 +1,96 @@
+/* Deterministic replay of a recorded transaction log
+ *
+ * usage: replay <log.json>
+ *
+ * The log is a JSON array of { "pkey": [4 words], "params": [words], "rand": [4 words] },
+ * words may be numbers or decimal strings. Every transaction is replayed through
+ * Transaction::decode and process against an empty in memory store, with only the tokens
+ * of CONFIG registered as in the wasm image the prover runs, and one JSON line is
+ * printed per step with the error, the clear_events output and the state of every player
+ * the step changed. Players are the signers of the log and the pids targeted by Deposit
+ * and GiftEnergy, so the owner paid by a BidCard or SellCard is included as well.
+ */
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use zkwasm_application::harness::Harness;
+use zkwasm_application::opcode::{DEPOSIT, GIFT_ENERGY};
+use zkwasm_application::player::AutomataPlayer;
+use zkwasm_application::state::{State, Transaction};
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Word {
+    Number(u64),
+    Text(String),
+}
+
+impl Word {
+    fn value(&self) -> u64 {
+        match self {
+            Word::Number(v) => *v,
+            Word::Text(s) => s.parse().unwrap_or_else(|_| panic!("invalid u64 word {}", s)),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Step {
+    pkey: [Word; 4],
+    params: Vec<Word>,
+    #[serde(default)]
+    rand: Option<[Word; 4]>,
+}
+
+// every pid the log can touch, the signers and the targets of Deposit and GiftEnergy
+fn players(steps: &[(Vec<u64>, [u64; 4], [u64; 4])]) -> Vec<[u64; 2]> {
+    let mut pids = vec![];
+    for (params, pkey, _) in steps.iter() {
+        pids.push(AutomataPlayer::pkey_to_pid(pkey));
+        let opcode = params.first().map_or(u64::MAX, |h| h & 0xff);
+        if (opcode == DEPOSIT || opcode == GIFT_ENERGY) && params.len() > 2 {
+            pids.push([params[1], params[2]]);
+        }
+    }
+    pids.sort();
+    pids.dedup();
+    pids
+}
+
+fn player_states(pids: &[[u64; 2]]) -> BTreeMap<[u64; 2], Value> {
+    pids.iter()
+        .filter_map(|pid| AutomataPlayer::load(pid).ok().flatten().map(|p| (*pid, serde_json::to_value(&p).unwrap())))
+        .collect()
+}
+
+fn main() {
+    let path = std::env::args().nth(1).expect("usage: replay <log.json>");
+    let log = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("can not read {}: {}", path, e));
+    let steps: Vec<Step> = serde_json::from_str(&log).expect("invalid transaction log");
+    let steps = steps.iter().map(|step| (
+        step.params.iter().map(|w| w.value()).collect::<Vec<_>>(),
+        step.pkey.each_ref().map(|w| w.value()),
+        step.rand.as_ref().map_or([0; 4], |r| r.each_ref().map(|w| w.value())),
+    )).collect::<Vec<_>>();
+    let pids = players(&steps);
+
+    // no test token, a step only replays as it ran on chain with the production registry
+    let mut harness = Harness::with_tokens(&[]);
+    let mut before = player_states(&pids);
+    for (index, (params, pkey, rand)) in steps.iter().enumerate() {
+        let events = harness.submit(pkey, params, rand);
+        let error = events[0] as u32;
+        let after = player_states(&pids);
+        let changed = after.iter().filter(|(pid, p)| before.get(*pid) != Some(*p)).map(|(_, p)| p.clone());
+        println!("{}", json!({
+            "step": index,
+            "params": params.iter().map(|x| x.to_string()).collect::<Vec<_>>(),
+            "error": error,
+            "error_name": if error == 0 { "" } else { Transaction::decode_error(error) },
+            "events": events.iter().map(|x| x.to_string()).collect::<Vec<_>>(),
+            "counter": State::counter(),
+            "players": changed.collect::<Vec<_>>(),
+        }));
+        before = after;
+    }
+}
@@ -0,0 +1,183 @@
+/* Economy simulator
+ *
+ * usage: simulate [players] [ticks] [seed] [strategy] [report_every]
+ *
+ * Installs synthetic players that follow a strategy (idle, builder, crafter, bounty,
+ * energy or mixed), runs the queue for the given number of ticks and prints one CSV
+ * row of economy statistics every report_every ticks.
+ */
+use zkwasm_application::config::CONFIG;
+use zkwasm_application::encode;
+use zkwasm_application::harness::{Harness, Rng};
+use zkwasm_application::player::PlayerData;
+use zkwasm_application::state::State;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Strategy {
+    Idle,     // only installs the player
+    Builder,  // installs and restarts objects with the cards at hand
+    Crafter,  // crafts new cards, then builds like Builder
+    Bounty,   // redeems surplus resources for treasure
+    Energy,   // collects energy whenever possible
+}
+
+impl Strategy {
+    fn parse(name: &str) -> Option<Vec<Strategy>> {
+        match name {
+            "idle" => Some(vec![Strategy::Idle]),
+            "builder" => Some(vec![Strategy::Builder]),
+            "crafter" => Some(vec![Strategy::Crafter]),
+            "bounty" => Some(vec![Strategy::Bounty]),
+            "energy" => Some(vec![Strategy::Energy]),
+            "mixed" => Some(vec![
+                Strategy::Idle,
+                Strategy::Builder,
+                Strategy::Crafter,
+                Strategy::Bounty,
+                Strategy::Energy,
+            ]),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Stats {
+    actions: u64,
+    failed: u64,
+    bounty_rewards: u64,
+}
+
+fn random_modifiers(data: &PlayerData, rng: &mut Rng) -> [u8; 8] {
+    let n = data.cards.len() as u64;
+    [0; 8].map(|_: u8| rng.below(n) as u8)
+}
+
+fn build(h: &mut Harness, pkey: &[u64; 4], data: &PlayerData, rng: &mut Rng) -> u32 {
+    if let Some(index) = data.objects.iter().position(|o| o.is_halted()) {
+        let modifiers = random_modifiers(data, rng);
+        h.exec(pkey, |n| encode::restart_object(n, index as u64, modifiers))
+    } else {
+        let index = data.objects.len() as u64;
+        let modifiers = random_modifiers(data, rng);
+        h.exec(pkey, |n| encode::install_object(n, index, modifiers))
+    }
+}
+
+fn act(h: &mut Harness, pkey: &[u64; 4], strategy: Strategy, rng: &mut Rng, stats: &mut Stats) {
+    let data = match h.player(pkey) {
+        Some(data) => data,
+        None => return,
+    };
+    let e = match strategy {
+        Strategy::Idle => return,
+        Strategy::Builder => build(h, pkey, &data, rng),
+        Strategy::Crafter => {
+            if rng.below(2) == 0 {
+                let nonce = h.nonce(pkey);
+                h.submit(pkey, &encode::install_card(nonce), &rng.rand())[0] as u32
+            } else {
+                build(h, pkey, &data, rng)
+            }
+        }
+        Strategy::Bounty => {
+            let (index, _) = data.local.0[..7]
+                .iter()
+                .enumerate()
+                .max_by_key(|(_, v)| **v)
+                .unwrap();
+            let reward = CONFIG.get_bounty_reward(data.redeem_info[index] as u64);
+            let e = h.exec(pkey, |n| encode::bounty(n, index as u64));
+            if e == 0 {
+                stats.bounty_rewards += reward;
+            }
+            e
+        }
+        Strategy::Energy => h.exec(pkey, encode::collect_energy),
+    };
+    stats.actions += 1;
+    if e != 0 {
+        stats.failed += 1;
+    }
+}
+
+fn percentile(sorted: &[i64], p: usize) -> i64 {
+    if sorted.is_empty() {
+        0
+    } else {
+        sorted[(sorted.len() - 1) * p / 100]
+    }
+}
+
+fn report(tick: u64, players: &[[u64; 4]], h: &Harness, stats: &Stats) {
+    let mut treasure = vec![];
+    let mut levels = vec![];
+    let mut resources = 0i64;
+    let mut active = 0;
+    let mut halted = 0;
+    for pkey in players.iter() {
+        if let Some(data) = h.player(pkey) {
+            treasure.push(*data.local.0.last().unwrap());
+            levels.push(data.level as u64);
+            resources += data.local.0[..7].iter().sum::<i64>();
+            halted += data.objects.iter().filter(|o| o.is_halted()).count();
+            active += data.objects.iter().filter(|o| !o.is_halted()).count();
+        }
+    }
+    treasure.sort();
+    let n = levels.len().max(1) as f64;
+    println!(
+        "{},{},{:.2},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+        tick,
+        levels.len(),
+        levels.iter().sum::<u64>() as f64 / n,
+        levels.iter().max().unwrap_or(&0),
+        treasure.iter().sum::<i64>(),
+        percentile(&treasure, 0),
+        percentile(&treasure, 50),
+        percentile(&treasure, 90),
+        percentile(&treasure, 100),
+        resources,
+        active,
+        halted,
+        State::bounty_pool(),
+        stats.bounty_rewards,
+        stats.actions,
+        stats.failed,
+    );
+}
+
+fn main() {
+    let args = std::env::args().collect::<Vec<_>>();
+    let arg = |i: usize, default: u64| args.get(i).map_or(default, |x| x.parse().expect("invalid number"));
+    let players = arg(1, 1000);
+    let ticks = arg(2, 10000);
+    let seed = arg(3, 0);
+    let strategies = Strategy::parse(args.get(4).map_or("mixed", |x| x.as_str()))
+        .expect("strategy is one of idle, builder, crafter, bounty, energy, mixed");
+    let report_every = arg(5, 500).max(1);
+
+    let mut rng = Rng::new(seed);
+    let mut h = Harness::new();
+    let mut stats = Stats::default();
+    let players = (0..players)
+        .map(|i| Harness::player_key(4 * (i + 1)))
+        .collect::<Vec<_>>();
+    for pkey in players.iter() {
+        h.install_player(pkey);
+    }
+
+    println!("tick,players,avg_level,max_level,total_treasure,min_treasure,median_treasure,p90_treasure,max_treasure,total_resources,active_objects,halted_objects,bounty_pool,bounty_rewards,actions,failed_actions");
+    for tick in 0..ticks {
+        // every player acts about once per 100 ticks
+        for (i, pkey) in players.iter().enumerate() {
+            if rng.below(100) == 0 {
+                act(&mut h, pkey, strategies[i % strategies.len()], &mut rng, &mut stats);
+            }
+        }
+        h.tick();
+        if (tick + 1) % report_every == 0 {
+            report(tick + 1, &players, &h, &stats);
+        }
+    }
+}
@@ -1,13 +1,15 @@
 use crate::config::LOCAL_ATTRIBUTES_SIZE;
 use serde::Serialize;
-use crate::player::PlayerData;
+use crate::player::{AutomataPlayer, PlayerData};
 use std::slice::IterMut;
 use crate::error::*;
+use crate::storage::TryFromData;
 use zkwasm_rest_abi::StorageData;
 use zkwasm_rest_convention::BidInfo;
 use zkwasm_rest_convention::IndexedObject;
 use zkwasm_rest_convention::MarketInfo;
 use zkwasm_rest_convention::BidObject;
+use zkwasm_rest_convention::Wrapped;
 use std::marker::PhantomData;
 
 #[derive(Clone, Debug, Serialize)]
@@ -27,6 +29,16 @@ impl Card {
     }
 }
 
+impl TryFromData for Card {
+    fn try_from_data(u64data: &mut IterMut<u64>) -> Result<Self, u32> {
+        if u64data.len() < 3 {
+            Err(ERROR_INVALID_STORAGE_DATA)
+        } else {
+            Ok(Self::from_data(u64data))
+        }
+    }
+}
+
 impl StorageData for Card {
     fn from_data(u64data: &mut IterMut<u64>) -> Self {
         let duration = *u64data.next().unwrap();
@@ -90,6 +102,61 @@ impl MarketCard {
             user: PhantomData
         })
     }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn load(marketid: u64) -> Option<Wrapped<MarketCard>> {
+        MarketCard::get_object(marketid)
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn save(marketcard: &Wrapped<MarketCard>) {
+        marketcard.store();
+    }
+
+    // Native builds keep market cards in crate::storage under their own key
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(marketid: u64) -> Option<Wrapped<MarketCard>> {
+        let mut data = crate::storage::get(&[Self::PREFIX, marketid, Self::POSTFIX, 0]);
+        if data.is_empty() {
+            None
+        } else {
+            let marketcard = MarketCard::from_data(&mut data.iter_mut());
+            Some(MarketCard::new_object(marketcard, marketid))
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(marketcard: &Wrapped<MarketCard>) {
+        let mut data = vec![];
+        marketcard.data.to_data(&mut data);
+        let marketid = marketcard.data.0.marketid;
+        crate::storage::set(&[Self::PREFIX, marketid, Self::POSTFIX, 0], data.as_slice());
+    }
+
+    /// Places a bid of player at price with BidObject::replace_bidder and refunds the previous
+    /// bidder, who is returned so the caller can store it.
+    #[cfg(target_arch = "wasm32")]
+    pub fn replace_bidder(&mut self, player: &mut AutomataPlayer, price: u64) -> Result<Option<AutomataPlayer>, u32> {
+        let prev = player.as_player(|p| self.0.replace_bidder(p, price))?;
+        Ok(prev.map(AutomataPlayer::from_player))
+    }
+
+    /// Pays the current bid to the owner with BidObject::deal, the owner is returned so the
+    /// caller can store it
+    #[cfg(target_arch = "wasm32")]
+    pub fn deal(&mut self) -> Result<AutomataPlayer, u32> {
+        self.0.deal().map(AutomataPlayer::from_player)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn replace_bidder(&mut self, player: &mut AutomataPlayer, price: u64) -> Result<Option<AutomataPlayer>, u32> {
+        crate::harness::replace_bidder(&mut self.0, player, price)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn deal(&mut self) -> Result<AutomataPlayer, u32> {
+        crate::harness::deal(&self.0)
+    }
 }
 
 impl StorageData for MarketCard {
@@ -101,6 +168,23 @@ impl StorageData for MarketCard {
     }
 }
 
+// MarketInfo has a fixed size layout, so a full sample tells how many words a listing needs
+impl TryFromData for MarketCard {
+    fn try_from_data(u64data: &mut IterMut<u64>) -> Result<Self, u32> {
+        let mut sample = vec![];
+        let bid = BidInfo {
+            bidprice: 0,
+            bidder: [0, 0],
+        };
+        MarketCard::new(0, 0, 0, Some(bid), Card::new(0, [0; 8]), [0, 0]).to_data(&mut sample);
+        if u64data.len() < sample.len() {
+            Err(ERROR_INVALID_STORAGE_DATA)
+        } else {
+            Ok(Self::from_data(u64data))
+        }
+    }
+}
+
 impl IndexedObject<MarketCard> for MarketCard {
     const PREFIX: u64 = 0x1ee1;
     const POSTFIX: u64 = 0xfee1;
@@ -1,5 +1,9 @@
 use crate::card::Card;
+use crate::deposit::DEPOSIT_PENDING;
+use crate::error::{error_table, ErrorInfo, ERROR_CONFIG_OUT_OF_BOUNDS, ERROR_INVALID_PARAMS};
+use crate::storage;
 use serde::Serialize;
+use std::cell::RefCell;
 
 pub const ENTITY_ATTRIBUTES_SIZE: usize = 4; //level speed efficiency productivity
 pub const LOCAL_ATTRIBUTES_SIZE: usize = 8;
@@ -13,6 +17,13 @@ lazy_static::lazy_static! {
     };
 }
 
+/// A token players can deposit and withdraw, token_id is the id carried by WithdrawInfo
+#[derive(Serialize, Clone)]
+pub struct TokenInfo {
+    pub name: &'static str,
+    pub token_id: u64,
+}
+
 #[derive(Serialize, Clone)]
 pub struct Config {
     version: &'static str,
@@ -21,6 +32,31 @@ pub struct Config {
     bounty_cost_base: u64, // , 2, 4, 8, ....  index < 2: 0, index >=2:  cost_exp ^(level-2)
     bounty_reward_base: u64,
     redeem_energy_cooldown: u64,
+    cost_increase_round: u16,
+    initial_energy: u16,
+    deposit_policy: u64,
+    exclusive_cards: bool, // when set a card can only be used by one object at a time
+    interest_rates: Vec<(u16, u64)>, // (minimum level, daily rate in 1/10000 of the held balance)
+    interest_holding_period: u64, // ticks a balance must be held before interest can be claimed
+    interest_fee: u64,
+    interest_reserve: u64, // initial reserve interest is paid from, caps the total emission
+    exp_curve: Vec<(u16, u64, u64)>, // (from level, exp needed at that level, extra exp per level above it)
+    level_up_energy: u16,
+    level_up_treasure: u64,
+    level_up_card_rolls: u16,
+    energy_regen_period: u64, // ticks needed to regenerate one energy
+    energy_cap_base: u64, // passive regeneration stops at energy_cap_base + energy_cap_per_level * level
+    energy_cap_per_level: u64,
+    energy_gift_window: u64, // ticks in a gift window, one day by default
+    energy_gift_send_limit: u16, // energy a player can send per window, before tax
+    energy_gift_receive_limit: u16, // energy a player can receive per window, after tax
+    energy_gift_tax: u64, // percentage of a gift that is burned
+    energy_pack_size: u16,
+    energy_pack_price: u64,
+    deposit_refund_timeout: u64, // ticks before a pending deposit can be refunded to L1
+    tokens: Vec<TokenInfo>, // indexed by the token index of Deposit and Withdraw, token 0 is the in game treasure
+    params: Vec<ParamInfo>,
+    errors: Vec<ErrorInfo>,
 }
 
 /* bounty info
@@ -112,12 +148,51 @@ lazy_static::lazy_static! {
         entity_attributes: ["Level", "Speed", "Efficiency", "Producitivity"],
         local_attributes: ["Engery Crystal", "Instellar Mineral", "Biomass", "Quantum Foam", "Necrodermis", "Alien Floral", "Spice Melange", "Titanium"],
         redeem_energy_cooldown: 1000,
+        cost_increase_round: COST_INCREASE_ROUND,
+        initial_energy: INITIAL_ENERGY,
+        deposit_policy: DEPOSIT_PENDING,
+        exclusive_cards: false,
+        interest_rates: vec![(0, 0), (1, 1), (2, 2), (4, 4), (8, 6), (16, 8), (32, 10)],
+        interest_holding_period: 2880,
+        interest_fee: 100,
+        interest_reserve: 10000000,
+        exp_curve: vec![(0, 100, 10)],
+        level_up_energy: 10,
+        level_up_treasure: 100,
+        level_up_card_rolls: 1,
+        energy_regen_period: 60,
+        energy_cap_base: 100,
+        energy_cap_per_level: 20,
+        energy_gift_window: 17280,
+        energy_gift_send_limit: 200,
+        energy_gift_receive_limit: 200,
+        energy_gift_tax: 10,
+        energy_pack_size: 50,
+        energy_pack_price: 500,
+        deposit_refund_timeout: 17280 * 7,
+        tokens: registered_tokens(),
+        params: PARAMS.to_vec(),
+        errors: error_table(),
     };
 }
 
+// token 0 is the treasure
+fn registered_tokens() -> Vec<TokenInfo> {
+    vec![TokenInfo { name: "Titanium", token_id: 0 }]
+}
+
 impl Config {
     pub fn to_json_string() -> String {
-        serde_json::to_string(&CONFIG.clone()).unwrap()
+        let mut config = CONFIG.clone();
+        config.bounty_cost_base = param(PARAM_BOUNTY_COST_BASE);
+        config.bounty_reward_base = param(PARAM_BOUNTY_REWARD_BASE);
+        config.redeem_energy_cooldown = param(PARAM_REDEEM_ENERGY_COOLDOWN);
+        config.cost_increase_round = CONFIG.cost_increase_round();
+        config.initial_energy = CONFIG.initial_energy();
+        config.deposit_policy = CONFIG.deposit_policy();
+        config.exclusive_cards = CONFIG.exclusive_cards();
+        config.tokens = LIVE_TOKENS.0.borrow().clone();
+        serde_json::to_string(&config).unwrap()
     }
     pub fn autotick() -> bool {
         true
@@ -126,16 +201,210 @@ impl Config {
     pub fn get_bounty_cost(&self, redeem_info: u64) -> u64 {
         let mut cost = 20;
         for _ in 0..redeem_info {
-            cost = self.bounty_cost_base * cost
+            cost = param(PARAM_BOUNTY_COST_BASE).saturating_mul(cost)
         }
         return cost;
     }
 
     pub fn get_bounty_reward(&self, redeem_info: u64) -> u64 {
-        return self.bounty_reward_base * (redeem_info + 1);
+        return param(PARAM_BOUNTY_REWARD_BASE) * (redeem_info + 1);
     }
 
     pub fn get_redeem_energy_cooldown(&self) -> u64 {
-        return self.redeem_energy_cooldown;
+        return param(PARAM_REDEEM_ENERGY_COOLDOWN);
+    }
+
+    pub fn cost_increase_round(&self) -> u16 {
+        param(PARAM_COST_INCREASE_ROUND) as u16
+    }
+
+    pub fn initial_energy(&self) -> u16 {
+        param(PARAM_INITIAL_ENERGY) as u16
+    }
+
+    pub fn deposit_policy(&self) -> u64 {
+        param(PARAM_DEPOSIT_POLICY)
+    }
+
+    pub fn exclusive_cards(&self) -> bool {
+        param(PARAM_EXCLUSIVE_CARDS) != 0
+    }
+
+    pub fn get_interest_rate(&self, level: u16) -> u64 {
+        self.interest_rates
+            .iter()
+            .rev()
+            .find(|(min_level, _)| level >= *min_level)
+            .map_or(0, |(_, rate)| *rate)
+    }
+
+    pub fn get_interest_holding_period(&self) -> u64 {
+        self.interest_holding_period
+    }
+
+    pub fn get_interest_fee(&self) -> u64 {
+        self.interest_fee
+    }
+
+    pub fn get_interest_reserve(&self) -> u64 {
+        self.interest_reserve
+    }
+
+    /// Exp needed to go from level to level + 1, never 0 so a level up always consumes exp
+    pub fn get_exp_to_level_up(&self, level: u16) -> u64 {
+        self.exp_curve
+            .iter()
+            .rev()
+            .find(|(from, _, _)| level >= *from)
+            .map_or(0, |(from, base, step)| base.saturating_add(step.saturating_mul((level - from) as u64)))
+            .max(1)
+    }
+
+    pub fn get_level_up_energy(&self) -> u16 {
+        self.level_up_energy
+    }
+
+    pub fn get_level_up_treasure(&self) -> u64 {
+        self.level_up_treasure
+    }
+
+    pub fn get_level_up_card_rolls(&self) -> u16 {
+        self.level_up_card_rolls
+    }
+
+    pub fn get_energy_regen_period(&self) -> u64 {
+        self.energy_regen_period.max(1)
+    }
+
+    pub fn get_energy_gift_window(&self) -> u64 {
+        self.energy_gift_window.max(1)
+    }
+
+    pub fn get_energy_gift_limits(&self) -> (u16, u16) {
+        (self.energy_gift_send_limit, self.energy_gift_receive_limit)
+    }
+
+    /// Energy burned when amount is gifted, rounded up so small gifts are taxed too
+    pub fn get_energy_gift_tax(&self, amount: u16) -> u16 {
+        ((amount as u64 * self.energy_gift_tax.min(100) + 99) / 100) as u16
+    }
+
+    pub fn get_energy_pack(&self) -> (u16, u64) {
+        (self.energy_pack_size, self.energy_pack_price)
+    }
+
+    pub fn get_deposit_refund_timeout(&self) -> u64 {
+        self.deposit_refund_timeout
+    }
+
+    pub fn get_token(&self, index: u64) -> Option<TokenInfo> {
+        LIVE_TOKENS.0.borrow().get(index as usize).cloned()
+    }
+
+    pub fn token_count(&self) -> usize {
+        LIVE_TOKENS.0.borrow().len()
+    }
+
+    pub fn get_max_energy(&self, level: u16) -> u16 {
+        let cap = self.energy_cap_base.saturating_add(self.energy_cap_per_level.saturating_mul(level as u64));
+        cap.min(0xffff) as u16
+    }
+}
+
+/* Token registry
+ *
+ * The tokens of CONFIG are the ones every build registers. The registry below is what the
+ * handlers read, the native harness appends its test tokens to it before it resets the state.
+ */
+pub struct SafeTokens(RefCell<Vec<TokenInfo>>);
+unsafe impl Sync for SafeTokens {}
+
+lazy_static::lazy_static! {
+    static ref LIVE_TOKENS: SafeTokens = SafeTokens(RefCell::new(CONFIG.tokens.clone()));
+}
+
+/// Restores the registered tokens and appends extra, the new tokens get the next indices
+pub fn set_extra_tokens(extra: &[TokenInfo]) {
+    let mut tokens = CONFIG.tokens.clone();
+    tokens.extend_from_slice(extra);
+    *LIVE_TOKENS.0.borrow_mut() = tokens;
+}
+
+/* Runtime parameters
+ *
+ * The parameters below can be updated by a config manager with SetConfig. The live
+ * values are cached in LIVE_PARAMS and stored at PARAMS_KEY in the merkle map, the
+ * matching fields of CONFIG only hold their defaults.
+ */
+pub const PARAM_BOUNTY_COST_BASE: u64 = 0;
+pub const PARAM_BOUNTY_REWARD_BASE: u64 = 1;
+pub const PARAM_REDEEM_ENERGY_COOLDOWN: u64 = 2;
+pub const PARAM_COST_INCREASE_ROUND: u64 = 3;
+pub const PARAM_INITIAL_ENERGY: u64 = 4;
+pub const PARAM_DEPOSIT_POLICY: u64 = 5; // one of the DEPOSIT_* policies in crate::deposit
+pub const PARAM_EXCLUSIVE_CARDS: u64 = 6; // 1 when a card can only be used by one object
+
+#[derive(Serialize, Clone)]
+pub struct ParamInfo {
+    pub index: u64,
+    pub name: &'static str,
+    pub min: u64,
+    pub max: u64,
+}
+
+pub static PARAMS: [ParamInfo; 7] = [
+    ParamInfo { index: PARAM_BOUNTY_COST_BASE, name: "bounty_cost_base", min: 1, max: 16 },
+    ParamInfo { index: PARAM_BOUNTY_REWARD_BASE, name: "bounty_reward_base", min: 1, max: 1000 },
+    ParamInfo { index: PARAM_REDEEM_ENERGY_COOLDOWN, name: "redeem_energy_cooldown", min: 1, max: 120960 },
+    ParamInfo { index: PARAM_COST_INCREASE_ROUND, name: "cost_increase_round", min: 1, max: 64 },
+    ParamInfo { index: PARAM_INITIAL_ENERGY, name: "initial_energy", min: 0, max: 1000 },
+    ParamInfo { index: PARAM_DEPOSIT_POLICY, name: "deposit_policy", min: 0, max: 2 },
+    ParamInfo { index: PARAM_EXCLUSIVE_CARDS, name: "exclusive_cards", min: 0, max: 1 },
+];
+
+const PARAMS_KEY: [u64; 4] = [0xc0f1, 0, 0, 0xc0f2];
+
+pub struct SafeParams(RefCell<[u64; 7]>);
+unsafe impl Sync for SafeParams {}
+
+lazy_static::lazy_static! {
+    static ref LIVE_PARAMS: SafeParams = SafeParams(RefCell::new(default_params()));
+}
+
+fn default_params() -> [u64; 7] {
+    [
+        CONFIG.bounty_cost_base,
+        CONFIG.bounty_reward_base,
+        CONFIG.redeem_energy_cooldown,
+        CONFIG.cost_increase_round as u64,
+        CONFIG.initial_energy as u64,
+        CONFIG.deposit_policy,
+        CONFIG.exclusive_cards as u64,
+    ]
+}
+
+pub fn param(index: u64) -> u64 {
+    LIVE_PARAMS.0.borrow()[index as usize]
+}
+
+/// Loads the stored values, parameters that were never updated keep their default
+pub fn load_params() {
+    let mut params = default_params();
+    for (p, v) in params.iter_mut().zip(storage::get(&PARAMS_KEY).iter()) {
+        *p = *v;
+    }
+    *LIVE_PARAMS.0.borrow_mut() = params;
+}
+
+/// Checks value against the bounds of the parameter and stores it, returns the previous value
+pub fn update_param(index: u64, value: u64) -> Result<u64, u32> {
+    let info = PARAMS.get(index as usize).ok_or(ERROR_INVALID_PARAMS)?;
+    if value < info.min || value > info.max {
+        return Err(ERROR_CONFIG_OUT_OF_BOUNDS);
     }
+    let mut params = LIVE_PARAMS.0.borrow_mut();
+    let old = params[index as usize];
+    params[index as usize] = value;
+    storage::set(&PARAMS_KEY, params.as_slice());
+    Ok(old)
 }
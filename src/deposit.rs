@@ -0,0 +1,86 @@
+use crate::config::CONFIG;
+use crate::error::ERROR_INVALID_STORAGE_DATA;
+use crate::storage;
+use crate::storage::TryFromData;
+use serde::Serialize;
+use std::slice::IterMut;
+
+/* Pending deposits
+ *
+ * Deposits for a pid without a player are kept at [PENDING_PREFIX, pid[0], pid[1], PENDING_POSTFIX]
+ * when the deposit policy is DEPOSIT_PENDING, and credited when the pid installs its player.
+ * Entries older than the refund timeout can be sent back to their L1 address by RefundDeposit,
+ * or to the address given in the command when the deposit did not carry its sender.
+ * The record is the number of entries followed by token, amount, counter, txref and the three
+ * address words of each.
+ */
+pub const DEPOSIT_REJECT: u64 = 0; // the deposit fails with ERROR_PLAYER_NOT_EXIST
+pub const DEPOSIT_CREATE: u64 = 1; // a default player is created and credited
+pub const DEPOSIT_PENDING: u64 = 2; // the amount waits in a pending deposit until InstallPlayer
+
+const PENDING_PREFIX: u64 = 0xde01;
+const PENDING_POSTFIX: u64 = 0xde02;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PendingDeposit {
+    pub token: u64,
+    pub amount: u64,
+    pub counter: u64, // queue counter when the deposit arrived
+    pub txref: u64, // L1 transaction reference of the deposit
+    pub address: [u64; 3], // L1 sender in the WithdrawInfo layout with a zero amount
+}
+
+impl PendingDeposit {
+    /// L1 address an expired deposit is refunded to, fallback is used when no sender was recorded
+    pub fn refund_address(&self, counter: u64, fallback: &[u64; 3]) -> Option<[u64; 3]> {
+        if counter < self.counter.saturating_add(CONFIG.get_deposit_refund_timeout()) {
+            None
+        } else if self.address != [0; 3] {
+            Some(self.address)
+        } else if *fallback != [0; 3] {
+            Some(*fallback)
+        } else {
+            None
+        }
+    }
+}
+
+impl TryFromData for PendingDeposit {
+    fn try_from_data(u64data: &mut IterMut<u64>) -> Result<Self, u32> {
+        let mut next = || u64data.next().map(|x| *x).ok_or(ERROR_INVALID_STORAGE_DATA);
+        Ok(PendingDeposit {
+            token: next()?,
+            amount: next()?,
+            counter: next()?,
+            txref: next()?,
+            address: [next()?, next()?, next()?],
+        })
+    }
+}
+
+fn key(pid: &[u64; 2]) -> [u64; 4] {
+    [PENDING_PREFIX, pid[0], pid[1], PENDING_POSTFIX]
+}
+
+pub fn load(pid: &[u64; 2]) -> Result<Vec<PendingDeposit>, u32> {
+    let mut data = storage::get(&key(pid));
+    let mut data = data.iter_mut();
+    let size = match data.next() {
+        Some(size) => *size,
+        None => return Ok(vec![]),
+    };
+    let mut deposits = vec![];
+    for _ in 0..size {
+        deposits.push(PendingDeposit::try_from_data(&mut data)?);
+    }
+    Ok(deposits)
+}
+
+pub fn save(pid: &[u64; 2], deposits: &[PendingDeposit]) {
+    let mut data = vec![deposits.len() as u64];
+    for d in deposits.iter() {
+        data.extend_from_slice(&[d.token, d.amount, d.counter, d.txref]);
+        data.extend_from_slice(&d.address);
+    }
+    storage::set(&key(pid), data.as_slice());
+}
@@ -0,0 +1,140 @@
+use crate::opcode::*;
+
+/* Client side encoding of transaction params
+ *
+ * Every function returns the params words of one command, header included, in the
+ * layout expected by Transaction::decode.
+ */
+
+fn build(code: u64, nonce: u64, args: &[u64]) -> Vec<u64> {
+    let opcode = Opcode::lookup(code).unwrap();
+    assert!(args.len() >= opcode.params && args.len() <= opcode.params + opcode.optional, "check params size of {}", opcode.name);
+    let mut params = Vec::with_capacity(args.len() + 1);
+    params.push(opcode.header(nonce));
+    params.extend_from_slice(args);
+    params
+}
+
+/// Packs the eight card indices of an object, modifiers[0] goes to the lowest byte
+pub fn encode_modifier(modifiers: [u8; 8]) -> u64 {
+    u64::from_le_bytes(modifiers)
+}
+
+/// Packs an amount and an L1 address into the three words used by WithdrawInfo
+pub fn encode_withdraw_info(amount: u64, address: [u8; 20]) -> [u64; 3] {
+    let first = u32::from_le_bytes(address[0..4].try_into().unwrap()) as u64;
+    let second = u64::from_le_bytes(address[4..12].try_into().unwrap());
+    let third = u64::from_le_bytes(address[12..20].try_into().unwrap());
+    [(first << 32) | (amount & 0xffffffff), second, third]
+}
+
+pub fn tick(nonce: u64) -> Vec<u64> {
+    build(TICK, nonce, &[])
+}
+
+pub fn install_player(nonce: u64) -> Vec<u64> {
+    build(INSTALL_PLAYER, nonce, &[])
+}
+
+pub fn install_object(nonce: u64, object_index: u64, modifiers: [u8; 8]) -> Vec<u64> {
+    build(INSTALL_OBJECT, nonce, &[object_index, encode_modifier(modifiers)])
+}
+
+pub fn restart_object(nonce: u64, object_index: u64, modifiers: [u8; 8]) -> Vec<u64> {
+    build(RESTART_OBJECT, nonce, &[object_index, encode_modifier(modifiers)])
+}
+
+pub fn upgrade_object(nonce: u64, object_index: u64, feature_index: u64) -> Vec<u64> {
+    build(UPGRADE_OBJECT, nonce, &[object_index, feature_index])
+}
+
+pub fn install_card(nonce: u64) -> Vec<u64> {
+    build(INSTALL_CARD, nonce, &[])
+}
+
+pub fn withdraw(nonce: u64, data: [u64; 3]) -> Vec<u64> {
+    withdraw_token(nonce, 0, data)
+}
+
+pub fn withdraw_token(nonce: u64, token: u64, data: [u64; 3]) -> Vec<u64> {
+    build(WITHDRAW, nonce, &[token, data[0], data[1], data[2]])
+}
+
+pub fn deposit(nonce: u64, pid: [u64; 2], amount: u64) -> Vec<u64> {
+    deposit_token(nonce, pid, 0, amount, 0, [0; 3])
+}
+
+/// sender is the L1 address packed by encode_withdraw_info with a zero amount.
+/// A zero txref and sender are left out, which is the layout sent by the ts PlayerConvention.
+pub fn deposit_token(nonce: u64, pid: [u64; 2], token: u64, amount: u64, txref: u64, sender: [u64; 3]) -> Vec<u64> {
+    if txref == 0 && sender == [0; 3] {
+        build(DEPOSIT, nonce, &[pid[0], pid[1], token, amount])
+    } else {
+        build(DEPOSIT, nonce, &[pid[0], pid[1], token, amount, txref, sender[0], sender[1], sender[2]])
+    }
+}
+
+pub fn bounty(nonce: u64, bounty_index: u64) -> Vec<u64> {
+    build(BOUNTY, nonce, &[bounty_index])
+}
+
+pub fn collect_energy(nonce: u64) -> Vec<u64> {
+    build(COLLECT_ENERGY, nonce, &[])
+}
+
+pub fn list_card_in_market(nonce: u64, card_index: u64, ask_price: u64) -> Vec<u64> {
+    build(LIST_CARD_IN_MARKET, nonce, &[card_index, ask_price])
+}
+
+pub fn bid_card(nonce: u64, market_index: u64, price: u64) -> Vec<u64> {
+    build(BID_CARD, nonce, &[market_index, price])
+}
+
+pub fn sell_card(nonce: u64, card_index: u64) -> Vec<u64> {
+    build(SELL_CARD, nonce, &[card_index])
+}
+
+pub fn gift_energy(nonce: u64, receiver: [u64; 2], amount: u64) -> Vec<u64> {
+    build(GIFT_ENERGY, nonce, &[receiver[0], receiver[1], amount])
+}
+
+pub fn buy_energy_pack(nonce: u64, packs: u64) -> Vec<u64> {
+    build(BUY_ENERGY_PACK, nonce, &[packs])
+}
+
+pub fn claim_withdraw(nonce: u64) -> Vec<u64> {
+    build(CLAIM_WITHDRAW, nonce, &[])
+}
+
+pub fn cancel_withdraw(nonce: u64) -> Vec<u64> {
+    build(CANCEL_WITHDRAW, nonce, &[])
+}
+
+pub fn set_withdraw_limits(nonce: u64, window: u64, player_limit: u64, global_limit: u64, delay: u64) -> Vec<u64> {
+    build(SET_WITHDRAW_LIMITS, nonce, &[window, player_limit, global_limit, delay])
+}
+
+pub fn set_pause(nonce: u64, flags: u64) -> Vec<u64> {
+    build(SET_PAUSE, nonce, &[flags])
+}
+
+pub fn add_admin(nonce: u64, pid: [u64; 2], roles: u64) -> Vec<u64> {
+    build(ADD_ADMIN, nonce, &[pid[0], pid[1], roles])
+}
+
+pub fn remove_admin(nonce: u64, pid: [u64; 2]) -> Vec<u64> {
+    build(REMOVE_ADMIN, nonce, &[pid[0], pid[1]])
+}
+
+pub fn set_config(nonce: u64, param: u64, value: u64) -> Vec<u64> {
+    build(SET_CONFIG, nonce, &[param, value])
+}
+
+pub fn refund_deposit(nonce: u64, pid: [u64; 2]) -> Vec<u64> {
+    build(REFUND_DEPOSIT, nonce, &[pid[0], pid[1]])
+}
+
+/// address, packed by encode_withdraw_info with a zero amount, receives the deposits without a sender
+pub fn refund_deposit_to(nonce: u64, pid: [u64; 2], address: [u64; 3]) -> Vec<u64> {
+    build(REFUND_DEPOSIT, nonce, &[pid[0], pid[1], address[0], address[1], address[2]])
+}
@@ -1,10 +1,220 @@
-pub const ERROR_PLAYER_ALREADY_EXIST: u32 = 1;
-pub const ERROR_PLAYER_NOT_EXIST: u32 = 2;
-pub const ERROR_NOT_ENOUGH_BALANCE: u32 = 3;
-pub const ERROR_INDEX_OUT_OF_BOUND: u32 = 4;
-pub const ERROR_NOT_ENOUGH_RESOURCE: u32 = 5;
-pub const ERROR_NOT_ENOUGH_LEVEL: u32 = 6;
-pub const ERROR_NOT_ENOUGH_POOL: u32 = 7;
-pub const ERROR_CARD_IS_IN_USE: u32 = 8;
-pub const ERROR_BID_PRICE_INSUFFICIENT: u32 = 9;
-pub const ERROR_NO_BIDDER: u32 = 10;
+use serde::Serialize;
+
+/// Errors returned by Transaction::process, the numeric codes are part of the client
+/// protocol and must never be renumbered
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Error {
+    PlayerAlreadyExist = 1,
+    PlayerNotExist = 2,
+    NotEnoughBalance = 3,
+    IndexOutOfBound = 4,
+    NotEnoughResource = 5,
+    NotEnoughLevel = 6,
+    NotEnoughPool = 7,
+    CardIsInUse = 8,
+    BidPriceInsufficient = 9,
+    NoBidder = 10,
+    InvalidStorageData = 11,
+    UnknownCommand = 12,
+    InvalidParams = 13,
+    ObjectAtMaxLevel = 14,
+    MarketNotExist = 15,
+    CardIndexOutOfRange = 16,
+    CardIsListed = 17,
+    CardSharedByObjects = 18,
+    InterestNotMatured = 19,
+    InterestReserveExhausted = 20,
+    NotEnoughEnergy = 21,
+    EnergyGiftLimitExceeded = 22,
+    EnergyCapExceeded = 23,
+    WithdrawLimitExceeded = 24,
+    WithdrawPending = 25,
+    NoPendingWithdraw = 26,
+    WithdrawNotMatured = 27,
+    Paused = 28,
+    NotAuthorized = 29,
+    ConfigOutOfBounds = 30,
+    BalanceOverflow = 31,
+    NoRefundableDeposit = 32,
+    InterestBelowFee = 33,
+}
+
+pub const ERRORS: &[Error] = &[
+    Error::PlayerAlreadyExist,
+    Error::PlayerNotExist,
+    Error::NotEnoughBalance,
+    Error::IndexOutOfBound,
+    Error::NotEnoughResource,
+    Error::NotEnoughLevel,
+    Error::NotEnoughPool,
+    Error::CardIsInUse,
+    Error::BidPriceInsufficient,
+    Error::NoBidder,
+    Error::InvalidStorageData,
+    Error::UnknownCommand,
+    Error::InvalidParams,
+    Error::ObjectAtMaxLevel,
+    Error::MarketNotExist,
+    Error::CardIndexOutOfRange,
+    Error::CardIsListed,
+    Error::CardSharedByObjects,
+    Error::InterestNotMatured,
+    Error::InterestReserveExhausted,
+    Error::NotEnoughEnergy,
+    Error::EnergyGiftLimitExceeded,
+    Error::EnergyCapExceeded,
+    Error::WithdrawLimitExceeded,
+    Error::WithdrawPending,
+    Error::NoPendingWithdraw,
+    Error::WithdrawNotMatured,
+    Error::Paused,
+    Error::NotAuthorized,
+    Error::ConfigOutOfBounds,
+    Error::BalanceOverflow,
+    Error::NoRefundableDeposit,
+    Error::InterestBelowFee,
+];
+
+impl Error {
+    pub fn code(self) -> u32 {
+        self as u32
+    }
+
+    pub fn from_code(code: u32) -> Option<Error> {
+        ERRORS.iter().find(|e| e.code() == code).copied()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Error::PlayerAlreadyExist => "PlayerAlreadyExist",
+            Error::PlayerNotExist => "PlayerNotExist",
+            Error::NotEnoughBalance => "NotEnoughBalance",
+            Error::IndexOutOfBound => "IndexOutofBound",
+            Error::NotEnoughResource => "NotEnoughResource",
+            Error::NotEnoughLevel => "NotEnoughLevel",
+            Error::NotEnoughPool => "NotEnoughFundInPool",
+            Error::CardIsInUse => "CardIsInUse",
+            Error::BidPriceInsufficient => "BidPriceInSufficient",
+            Error::NoBidder => "NoBidder",
+            Error::InvalidStorageData => "InvalidStorageData",
+            Error::UnknownCommand => "UnknownCommand",
+            Error::InvalidParams => "InvalidParams",
+            Error::ObjectAtMaxLevel => "ObjectAtMaxLevel",
+            Error::MarketNotExist => "MarketNotExist",
+            Error::CardIndexOutOfRange => "CardIndexOutOfRange",
+            Error::CardIsListed => "CardIsListed",
+            Error::CardSharedByObjects => "CardSharedByObjects",
+            Error::InterestNotMatured => "InterestNotMatured",
+            Error::InterestReserveExhausted => "InterestReserveExhausted",
+            Error::NotEnoughEnergy => "NotEnoughEnergy",
+            Error::EnergyGiftLimitExceeded => "EnergyGiftLimitExceeded",
+            Error::EnergyCapExceeded => "EnergyCapExceeded",
+            Error::WithdrawLimitExceeded => "WithdrawLimitExceeded",
+            Error::WithdrawPending => "WithdrawPending",
+            Error::NoPendingWithdraw => "NoPendingWithdraw",
+            Error::WithdrawNotMatured => "WithdrawNotMatured",
+            Error::Paused => "Paused",
+            Error::NotAuthorized => "NotAuthorized",
+            Error::ConfigOutOfBounds => "ConfigOutOfBounds",
+            Error::BalanceOverflow => "BalanceOverflow",
+            Error::NoRefundableDeposit => "NoRefundableDeposit",
+            Error::InterestBelowFee => "InterestBelowFee",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Error::PlayerAlreadyExist => "the player is already installed",
+            Error::PlayerNotExist => "the player is not installed",
+            Error::NotEnoughBalance => "the treasure balance does not cover the cost",
+            Error::IndexOutOfBound => "an object, card, feature or bounty index is out of range",
+            Error::NotEnoughResource => "the resource does not cover the bounty cost",
+            Error::NotEnoughLevel => "the player level is too low for this action",
+            Error::NotEnoughPool => "the bounty pool does not cover the withdrawal",
+            Error::CardIsInUse => "the card is used by an object or listed in the market",
+            Error::BidPriceInsufficient => "the bid does not exceed the current bid",
+            Error::NoBidder => "the listing has no bid to settle",
+            Error::InvalidStorageData => "a stored record is malformed",
+            Error::UnknownCommand => "the transaction opcode is unknown",
+            Error::InvalidParams => "the transaction params are malformed",
+            Error::ObjectAtMaxLevel => "the object can not be upgraded any further",
+            Error::MarketNotExist => "the market listing does not exist",
+            Error::CardIndexOutOfRange => "a modifier refers to a card the player does not own",
+            Error::CardIsListed => "a modifier refers to a card listed in the market",
+            Error::CardSharedByObjects => "a modifier refers to a card already used by another object",
+            Error::InterestNotMatured => "the balance has not been held for the minimum interest period",
+            Error::InterestReserveExhausted => "the interest reserve has been paid out",
+            Error::NotEnoughEnergy => "the energy does not cover the gift",
+            Error::EnergyGiftLimitExceeded => "the daily energy gift limit is reached",
+            Error::EnergyCapExceeded => "the energy would exceed the 0xffff cap",
+            Error::WithdrawLimitExceeded => "the withdrawal exceeds the player or global limit of the window",
+            Error::WithdrawPending => "the player already has a queued withdrawal",
+            Error::NoPendingWithdraw => "the player has no queued withdrawal",
+            Error::WithdrawNotMatured => "the queued withdrawal has not matured yet",
+            Error::Paused => "the command is paused by the admin",
+            Error::NotAuthorized => "the signer does not hold the admin role of the command",
+            Error::ConfigOutOfBounds => "the config value is outside the bounds of the parameter",
+            Error::BalanceOverflow => "the deposit would overflow a balance or a pool",
+            Error::NoRefundableDeposit => "no pending deposit of the pid has passed the refund timeout",
+            Error::InterestBelowFee => "the accrued interest does not cover the claim fee",
+        }
+    }
+
+    pub fn decode(code: u32) -> &'static str {
+        Error::from_code(code).map_or("Unknown", Error::name)
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct ErrorInfo {
+    code: u32,
+    name: &'static str,
+    description: &'static str,
+}
+
+/// Full code table, exported to clients through Config
+pub fn error_table() -> Vec<ErrorInfo> {
+    ERRORS
+        .iter()
+        .map(|e| ErrorInfo {
+            code: e.code(),
+            name: e.name(),
+            description: e.description(),
+        })
+        .collect()
+}
+
+pub const ERROR_PLAYER_ALREADY_EXIST: u32 = Error::PlayerAlreadyExist as u32;
+pub const ERROR_PLAYER_NOT_EXIST: u32 = Error::PlayerNotExist as u32;
+pub const ERROR_NOT_ENOUGH_BALANCE: u32 = Error::NotEnoughBalance as u32;
+pub const ERROR_INDEX_OUT_OF_BOUND: u32 = Error::IndexOutOfBound as u32;
+pub const ERROR_NOT_ENOUGH_RESOURCE: u32 = Error::NotEnoughResource as u32;
+pub const ERROR_NOT_ENOUGH_LEVEL: u32 = Error::NotEnoughLevel as u32;
+pub const ERROR_NOT_ENOUGH_POOL: u32 = Error::NotEnoughPool as u32;
+pub const ERROR_CARD_IS_IN_USE: u32 = Error::CardIsInUse as u32;
+pub const ERROR_BID_PRICE_INSUFFICIENT: u32 = Error::BidPriceInsufficient as u32;
+pub const ERROR_NO_BIDDER: u32 = Error::NoBidder as u32;
+pub const ERROR_INVALID_STORAGE_DATA: u32 = Error::InvalidStorageData as u32;
+pub const ERROR_UNKNOWN_COMMAND: u32 = Error::UnknownCommand as u32;
+pub const ERROR_INVALID_PARAMS: u32 = Error::InvalidParams as u32;
+pub const ERROR_OBJECT_AT_MAX_LEVEL: u32 = Error::ObjectAtMaxLevel as u32;
+pub const ERROR_MARKET_NOT_EXIST: u32 = Error::MarketNotExist as u32;
+pub const ERROR_CARD_INDEX_OUT_OF_RANGE: u32 = Error::CardIndexOutOfRange as u32;
+pub const ERROR_CARD_IS_LISTED: u32 = Error::CardIsListed as u32;
+pub const ERROR_CARD_SHARED_BY_OBJECTS: u32 = Error::CardSharedByObjects as u32;
+pub const ERROR_INTEREST_NOT_MATURED: u32 = Error::InterestNotMatured as u32;
+pub const ERROR_INTEREST_RESERVE_EXHAUSTED: u32 = Error::InterestReserveExhausted as u32;
+pub const ERROR_NOT_ENOUGH_ENERGY: u32 = Error::NotEnoughEnergy as u32;
+pub const ERROR_ENERGY_GIFT_LIMIT_EXCEEDED: u32 = Error::EnergyGiftLimitExceeded as u32;
+pub const ERROR_ENERGY_CAP_EXCEEDED: u32 = Error::EnergyCapExceeded as u32;
+pub const ERROR_WITHDRAW_LIMIT_EXCEEDED: u32 = Error::WithdrawLimitExceeded as u32;
+pub const ERROR_WITHDRAW_PENDING: u32 = Error::WithdrawPending as u32;
+pub const ERROR_NO_PENDING_WITHDRAW: u32 = Error::NoPendingWithdraw as u32;
+pub const ERROR_WITHDRAW_NOT_MATURED: u32 = Error::WithdrawNotMatured as u32;
+pub const ERROR_PAUSED: u32 = Error::Paused as u32;
+pub const ERROR_NOT_AUTHORIZED: u32 = Error::NotAuthorized as u32;
+pub const ERROR_CONFIG_OUT_OF_BOUNDS: u32 = Error::ConfigOutOfBounds as u32;
+pub const ERROR_BALANCE_OVERFLOW: u32 = Error::BalanceOverflow as u32;
+pub const ERROR_NO_REFUNDABLE_DEPOSIT: u32 = Error::NoRefundableDeposit as u32;
+pub const ERROR_INTEREST_BELOW_FEE: u32 = Error::InterestBelowFee as u32;
@@ -1,7 +1,45 @@
-use crate::player::AutomataPlayer;
+use crate::error::ERROR_INVALID_STORAGE_DATA;
+use crate::player::{AutomataPlayer, LevelUp};
+use crate::storage::TryFromData;
 use core::slice::IterMut;
+use std::cell::RefCell;
 use zkwasm_rest_abi::StorageData;
 use zkwasm_rest_convention::EventHandler;
+use zkwasm_rest_convention::insert_event;
+
+pub const EVENT_LEVEL_UP: u64 = 0x03;
+pub const EVENT_CONFIG_CHANGE: u64 = 0x04;
+pub const EVENT_DEPOSIT: u64 = 0x05;
+
+pub const DEPOSIT_CREDITED: u64 = 0;
+pub const DEPOSIT_CREATED: u64 = 1; // a player was created for the deposit
+pub const DEPOSIT_PENDED: u64 = 2; // kept as a pending deposit
+pub const DEPOSIT_CLAIMED: u64 = 3; // a pending deposit was credited on InstallPlayer
+
+/// Event layout: txref, pid[0], pid[1], token, amount, status
+pub fn emit_deposit(txref: u64, pid: &[u64; 2], token: u64, amount: u64, status: u64) {
+    let mut data = vec![txref, pid[0], pid[1], token, amount, status];
+    insert_event(EVENT_DEPOSIT, &mut data);
+}
+
+/// Event layout: pid[0], pid[1], level, energy, treasure, card_rolls
+pub fn emit_level_up(pid: &[u64; 2], reward: &LevelUp) {
+    let mut data = vec![
+        pid[0],
+        pid[1],
+        reward.level as u64,
+        reward.energy as u64,
+        reward.treasure,
+        reward.card_rolls as u64,
+    ];
+    insert_event(EVENT_LEVEL_UP, &mut data);
+}
+
+/// Event layout: param index, previous value, new value, counter
+pub fn emit_config_change(param: u64, old: u64, new: u64, counter: u64) {
+    let mut data = vec![param, old, new, counter];
+    insert_event(EVENT_CONFIG_CHANGE, &mut data);
+}
 
 #[derive(Clone)]
 pub struct Event {
@@ -27,6 +65,29 @@ impl StorageData for Event {
     }
 }
 
+impl TryFromData for Event {
+    fn try_from_data(u64data: &mut IterMut<u64>) -> Result<Self, u32> {
+        if u64data.len() < 3 {
+            Err(ERROR_INVALID_STORAGE_DATA)
+        } else {
+            Ok(Self::from_data(u64data))
+        }
+    }
+}
+
+pub struct SafeHalts(RefCell<u64>);
+unsafe impl Sync for SafeHalts {}
+
+lazy_static::lazy_static! {
+    // objects halted while STATE is borrowed by the queue, collected by the Tick handler
+    static ref HALTS: SafeHalts = SafeHalts(RefCell::new(0));
+}
+
+/// Number of objects halted by the events handled since the last call
+pub fn take_halts() -> u64 {
+    HALTS.0.replace(0)
+}
+
 impl EventHandler for Event {
     fn u64size() -> usize {
         3
@@ -40,13 +101,22 @@ impl EventHandler for Event {
     fn handle(&mut self, counter: u64) -> Option<Self> {
         let owner_id = self.owner;
         let object_index = self.object_index;
-        let mut player = AutomataPlayer::get_from_pid(&owner_id).unwrap();
+        let mut player = match AutomataPlayer::load(&owner_id) {
+            Ok(Some(player)) => player,
+            _ => return None, // the owner record is unusable, drop the event
+        };
+        player.data.regen_energy(counter);
         let m = if player.data.energy == 0 {
-            player.data.objects.get_mut(object_index).unwrap().halt();
+            if let Some(object) = player.data.objects.get_mut(object_index) {
+                object.halt(counter);
+            }
             None
         } else {
             player.data.apply_object_card(object_index, counter)
         };
+        if m.is_none() && player.data.objects.get(object_index).map_or(false, |o| o.is_halted()) {
+            *HALTS.0.borrow_mut() += 1;
+        }
         let event = if let Some(delta) = m {
             if player.data.objects[object_index].get_modifier_index() == 0 {
                 if player.data.energy < object_index as u16 + 1 {
@@ -0,0 +1,190 @@
+use crate::card::Card;
+use crate::config::{set_extra_tokens, TokenInfo, ADMIN_PUBKEY};
+use crate::encode;
+use crate::error::{ERROR_BID_PRICE_INSUFFICIENT, ERROR_NO_BIDDER, ERROR_PLAYER_NOT_EXIST};
+use crate::player::{AutomataPlayer, PlayerData};
+use crate::state::{State, Transaction};
+use crate::storage;
+use std::sync::{Mutex, MutexGuard};
+use zkwasm_rest_convention::{BidInfo, BidObject, MarketInfo, SettlementInfo, WithBalance};
+
+/* Native test harness
+ *
+ * Runs encoded transactions through Transaction::decode and process against the in
+ * memory backend of crate::storage, without the ts service, Redis or Mongo.
+ */
+
+lazy_static::lazy_static! {
+    static ref HARNESS_LOCK: Mutex<()> = Mutex::new(());
+}
+
+/// The game state is global, so only one harness is alive at a time:
+/// new() blocks until the previous one is dropped and then starts from an empty store.
+pub struct Harness {
+    _guard: MutexGuard<'static, ()>,
+}
+
+impl Default for Harness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Harness {
+    /// Starts from an empty store with the admin player installed
+    pub fn new() -> Self {
+        let mut harness = Self::empty();
+        harness.install_player(&Self::admin());
+        harness
+    }
+
+    /// Starts from an empty store without any player and with the tokens of CONFIG only,
+    /// as a fresh deployment does
+    pub fn empty() -> Self {
+        Self::with_tokens(&[])
+    }
+
+    /// Same as empty, with extra tokens registered after the tokens of CONFIG
+    pub fn with_tokens(extra: &[TokenInfo]) -> Self {
+        let guard = HARNESS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        set_extra_tokens(extra);
+        storage::clear();
+        State::reset();
+        SettlementInfo::flush_settlement();
+        Harness { _guard: guard }
+    }
+
+    /// A token only the harness registers, to reach the paths of tokens other than the treasure
+    pub fn test_token() -> TokenInfo {
+        TokenInfo { name: "TestToken", token_id: 1 }
+    }
+
+    pub fn admin() -> [u64; 4] {
+        *ADMIN_PUBKEY
+    }
+
+    /// Deterministic public key of the n-th test player
+    pub fn player_key(n: u64) -> [u64; 4] {
+        [n, n + 1, n + 2, n + 3]
+    }
+
+    /// Runs one transaction and returns the output of process, the first word is the error code
+    pub fn submit(&mut self, pkey: &[u64; 4], params: &[u64], rand: &[u64; 4]) -> Vec<u64> {
+        Transaction::decode(params).process(pkey, rand)
+    }
+
+    /// Runs one transaction with a zero random seed and returns its error code
+    pub fn run(&mut self, pkey: &[u64; 4], params: &[u64]) -> u32 {
+        self.submit(pkey, params, &[0; 4])[0] as u32
+    }
+
+    /// Same as run but encodes the transaction with the current nonce of the player
+    pub fn exec(&mut self, pkey: &[u64; 4], encoder: impl FnOnce(u64) -> Vec<u64>) -> u32 {
+        let nonce = self.nonce(pkey);
+        self.run(pkey, &encoder(nonce))
+    }
+
+    pub fn install_player(&mut self, pkey: &[u64; 4]) -> u32 {
+        self.run(pkey, &encode::install_player(0))
+    }
+
+    pub fn deposit(&mut self, pkey: &[u64; 4], amount: u64) -> u32 {
+        let pid = AutomataPlayer::pkey_to_pid(pkey);
+        self.exec(&Self::admin(), |nonce| encode::deposit(nonce, pid, amount))
+    }
+
+    pub fn tick(&mut self) {
+        let e = self.run(&Self::admin(), &encode::tick(0));
+        assert_eq!(e, 0, "tick failed with {}", Transaction::decode_error(e));
+    }
+
+    pub fn ticks(&mut self, n: u64) {
+        for _ in 0..n {
+            self.tick();
+        }
+    }
+
+    pub fn nonce(&self, pkey: &[u64; 4]) -> u64 {
+        AutomataPlayer::get(pkey).map_or(0, |p| p.nonce)
+    }
+
+    pub fn player(&self, pkey: &[u64; 4]) -> Option<PlayerData> {
+        AutomataPlayer::get(pkey).map(|p| p.data)
+    }
+
+    /// Stores the global state and loads it back as a restarted service would
+    pub fn reload(&mut self) {
+        State::store();
+        State::reset();
+        State::initialize();
+    }
+}
+
+/* Market settlement against the native store
+ *
+ * BidObject::replace_bidder and deal of zkwasm_rest_convention load the previous bidder and
+ * the owner from the merkle map. MarketCard calls them inside the zkWasm image and calls the
+ * functions below in native builds, which take the same steps on players of crate::storage.
+ */
+
+pub fn replace_bidder(
+    market: &mut MarketInfo<Card, PlayerData>,
+    player: &mut AutomataPlayer,
+    price: u64,
+) -> Result<Option<AutomataPlayer>, u32> {
+    let prev = market.get_bidder();
+    if prev.map_or(false, |b| b.bidprice >= price) {
+        return Err(ERROR_BID_PRICE_INSUFFICIENT);
+    }
+    player.data.cost_balance(price)?;
+    let prev_bidder = match prev {
+        Some(b) if b.bidder == player.player_id => {
+            player.data.inc_balance(b.bidprice);
+            None
+        },
+        Some(b) => {
+            let mut bidder = AutomataPlayer::load(&b.bidder)?.ok_or(ERROR_PLAYER_NOT_EXIST)?;
+            bidder.data.inc_balance(b.bidprice);
+            Some(bidder)
+        },
+        None => None,
+    };
+    market.set_bidder(Some(BidInfo {
+        bidprice: price,
+        bidder: player.player_id,
+    }));
+    Ok(prev_bidder)
+}
+
+pub fn deal(market: &MarketInfo<Card, PlayerData>) -> Result<AutomataPlayer, u32> {
+    let bid = market.get_bidder().ok_or(ERROR_NO_BIDDER)?;
+    let mut owner = AutomataPlayer::load(&market.get_owner())?.ok_or(ERROR_PLAYER_NOT_EXIST)?;
+    owner.data.inc_balance(bid.bidprice);
+    Ok(owner)
+}
+
+/// Deterministic splitmix64 generator for simulations and randomized tests
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform value in 0..n, n must not be zero
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    pub fn rand(&mut self) -> [u64; 4] {
+        [self.next_u64(), self.next_u64(), self.next_u64(), self.next_u64()]
+    }
+}
@@ -0,0 +1,122 @@
+use crate::config::CONFIG;
+use serde::Serialize;
+use std::cell::RefCell;
+use zkwasm_rest_abi::enforce;
+
+/* Bounty pool ledger
+ *
+ * Keeps per token what entered and left the bounty pools since genesis: the initial pool,
+ * deposits (pending ones included) and withdrawals net of cancelled ones (refunds included),
+ * so that for every token pool == initial + deposited - withdrawn. minted counts the treasure
+ * credited to players without a deposit behind it: bounty rewards, level up rewards and
+ * interest. The ledger is cached here instead of in State since level ups are granted while
+ * STATE is borrowed by the queue, it is stored as part of the global state record.
+ */
+#[derive(Clone, Default, Serialize)]
+pub struct PoolLedger {
+    pub initial: u64,
+    pub deposited: u64,
+    pub withdrawn: u64,
+}
+
+#[derive(Clone, Default)]
+pub struct Ledger {
+    pub minted: u64,
+    pub pools: Vec<PoolLedger>, // indexed by token like State::bounty_pools
+}
+
+pub struct SafeLedger(RefCell<Ledger>);
+unsafe impl Sync for SafeLedger {}
+
+lazy_static::lazy_static! {
+    static ref LEDGER: SafeLedger = SafeLedger(RefCell::new(Ledger::default()));
+}
+
+/// Starts a fresh ledger whose initial amounts are the given pools
+pub fn reset(pools: &[u64]) {
+    *LEDGER.0.borrow_mut() = Ledger {
+        minted: 0,
+        pools: pools.iter().map(|p| PoolLedger { initial: *p, ..Default::default() }).collect(),
+    };
+}
+
+pub fn get() -> Ledger {
+    LEDGER.0.borrow().clone()
+}
+
+pub fn set(ledger: Ledger) {
+    *LEDGER.0.borrow_mut() = ledger;
+}
+
+fn update(token: u64, f: impl FnOnce(&mut PoolLedger)) {
+    let mut ledger = LEDGER.0.borrow_mut();
+    let token = token as usize;
+    if ledger.pools.len() <= token {
+        ledger.pools.resize(token + 1, PoolLedger::default());
+    }
+    f(&mut ledger.pools[token]);
+}
+
+pub fn mint(amount: u64) {
+    let mut ledger = LEDGER.0.borrow_mut();
+    ledger.minted = ledger.minted.saturating_add(amount);
+}
+
+pub fn deposit(token: u64, amount: u64) {
+    update(token, |p| p.deposited += amount);
+}
+
+pub fn withdraw(token: u64, amount: u64) {
+    update(token, |p| p.withdrawn += amount);
+}
+
+/// Returns a cancelled withdrawal to the pool. Withdrawals queued before the ledger existed
+/// are already part of the initial amount, so cancelling them counts as a deposit.
+pub fn cancel_withdraw(token: u64, amount: u64) {
+    update(token, |p| {
+        if p.withdrawn >= amount {
+            p.withdrawn -= amount;
+        } else {
+            p.deposited += amount;
+        }
+    });
+}
+
+/// Halts if any pool drifted from what its deposits and withdrawals account for
+pub fn check(pools: &[u64]) {
+    let ledger = LEDGER.0.borrow();
+    enforce(ledger.pools.len() <= pools.len(), "check ledger tokens");
+    for (token, pool) in pools.iter().enumerate() {
+        let entry = ledger.pools.get(token).cloned().unwrap_or_default();
+        let expected = entry.initial as i128 + entry.deposited as i128 - entry.withdrawn as i128;
+        enforce(expected == *pool as i128, "check bounty pool ledger");
+    }
+}
+
+#[derive(Serialize)]
+struct PoolObserve {
+    token: &'static str,
+    #[serde(flatten)]
+    ledger: PoolLedger,
+    pool: u64,
+}
+
+#[derive(Serialize)]
+struct LedgerObserve {
+    minted: u64,
+    pools: Vec<PoolObserve>,
+}
+
+/// The ledger next to the current pool balances as json
+pub fn to_json_string(pools: &[u64]) -> String {
+    let ledger = get();
+    let observe = LedgerObserve {
+        minted: ledger.minted,
+        pools: pools.iter().enumerate().map(|(token, pool)| PoolObserve {
+            token: CONFIG.get_token(token as u64).map_or("", |t| t.name),
+            ledger: ledger.pools.get(token).cloned().unwrap_or_default(),
+            pool: *pool,
+        }).collect(),
+    };
+    serde_json::to_string(&observe).unwrap()
+}
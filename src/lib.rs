@@ -2,13 +2,21 @@
 #![feature(linked_list_cursors)]
 use wasm_bindgen::prelude::*;
 use zkwasm_rest_abi::*;
+pub mod admin;
 pub mod card;
 pub mod config;
+pub mod deposit;
+pub mod encode;
 pub mod error;
 pub mod events;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod harness;
+pub mod ledger;
 pub mod object;
+pub mod opcode;
 pub mod player;
 pub mod state;
+pub mod storage;
 
 use crate::config::Config;
 use crate::state::{State, Transaction};
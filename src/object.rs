@@ -1,3 +1,5 @@
+use crate::error::ERROR_INVALID_STORAGE_DATA;
+use crate::storage::TryFromData;
 use crate::StorageData;
 use serde::{Serialize, Serializer};
 use std::slice::IterMut;
@@ -13,7 +15,7 @@ where
 #[derive(Debug, Clone, Serialize)]
 pub struct Object {
     #[serde(serialize_with = "serialize_u64_as_string")]
-    pub modifier_info: u64, // running << 56 + (modifier index << 48) + counter
+    pub modifier_info: u64, // running << 56 + (modifier index << 48) + counter, the halt counter once halted
     pub cards: [u8; 8],       // u64 contains 8 cards
     pub attributes: [u16; 4], // level, speed, efficiency, productivity
 }
@@ -26,8 +28,8 @@ impl Object {
             attributes: [0, 0, 0, 0],
         }
     }
-    pub fn halt(&mut self) {
-        self.modifier_info = (self.modifier_info & 0xFFFFFFFFFFFFFF) | 1 << 56;
+    pub fn halt(&mut self, counter: u64) {
+        self.modifier_info = (self.modifier_info & 0xFF000000000000) | 1 << 56 | counter;
     }
 
     pub fn is_halted(&self) -> bool {
@@ -42,6 +44,10 @@ impl Object {
         return (self.modifier_info >> 48) & 0x7f;
     }
 
+    pub fn get_counter(&self) -> u64 {
+        self.modifier_info & 0xFFFFFFFFFFFF
+    }
+
     pub fn start_new_modifier(&mut self, modifier_index: usize, counter: u64) {
         self.modifier_info = ((modifier_index as u64) << 48) | counter;
     }
@@ -59,6 +65,16 @@ impl Object {
     }
 }
 
+impl TryFromData for Object {
+    fn try_from_data(u64data: &mut IterMut<u64>) -> Result<Self, u32> {
+        if u64data.len() < 3 {
+            Err(ERROR_INVALID_STORAGE_DATA)
+        } else {
+            Ok(Self::from_data(u64data))
+        }
+    }
+}
+
 impl StorageData for Object {
     fn from_data(u64data: &mut IterMut<u64>) -> Self {
         let modifier_info = *u64data.next().unwrap();
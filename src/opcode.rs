@@ -0,0 +1,72 @@
+/* Transaction layout
+ *
+ * params[0] = nonce << 16 | opcode, followed by the opcode specific words listed below.
+ * The same table drives Transaction::decode and the encoder, so they can not drift apart.
+ * Optional words were appended to an existing layout, older clients still send it without them.
+ */
+pub const TICK: u64 = 0;
+pub const INSTALL_PLAYER: u64 = 1;
+pub const INSTALL_OBJECT: u64 = 2; // object index, modifiers
+pub const RESTART_OBJECT: u64 = 3; // object index, modifiers
+pub const UPGRADE_OBJECT: u64 = 4; // object index, feature index
+pub const INSTALL_CARD: u64 = 5;
+pub const WITHDRAW: u64 = 6; // token index, withdraw info (3 words)
+pub const DEPOSIT: u64 = 7; // pid (2 words), token index, amount, optional L1 tx reference and L1 sender (3 words)
+pub const BOUNTY: u64 = 8; // bounty index
+pub const COLLECT_ENERGY: u64 = 9;
+pub const LIST_CARD_IN_MARKET: u64 = 10; // card index, ask price
+pub const BID_CARD: u64 = 11; // market index, price
+pub const SELL_CARD: u64 = 12; // card index
+pub const GIFT_ENERGY: u64 = 13; // receiver pid (2 words), amount
+pub const BUY_ENERGY_PACK: u64 = 14; // number of packs
+pub const CLAIM_WITHDRAW: u64 = 15;
+pub const CANCEL_WITHDRAW: u64 = 16;
+pub const SET_WITHDRAW_LIMITS: u64 = 17; // window, player limit, global limit, delay
+pub const SET_PAUSE: u64 = 18; // pause flags
+pub const ADD_ADMIN: u64 = 19; // pid (2 words), roles
+pub const REMOVE_ADMIN: u64 = 20; // pid (2 words)
+pub const SET_CONFIG: u64 = 21; // param index, value
+pub const REFUND_DEPOSIT: u64 = 22; // pid (2 words), optional L1 address (3 words) for deposits without a sender
+
+pub struct Opcode {
+    pub code: u64,
+    pub name: &'static str,
+    pub params: usize, // number of words following the header
+    pub optional: usize, // trailing words that may be left out, they decode as 0
+}
+
+pub static OPCODES: [Opcode; 23] = [
+    Opcode { code: TICK, name: "Tick", params: 0, optional: 0 },
+    Opcode { code: INSTALL_PLAYER, name: "InstallPlayer", params: 0, optional: 0 },
+    Opcode { code: INSTALL_OBJECT, name: "InstallObject", params: 2, optional: 0 },
+    Opcode { code: RESTART_OBJECT, name: "RestartObject", params: 2, optional: 0 },
+    Opcode { code: UPGRADE_OBJECT, name: "UpgradeObject", params: 2, optional: 0 },
+    Opcode { code: INSTALL_CARD, name: "InstallCard", params: 0, optional: 0 },
+    Opcode { code: WITHDRAW, name: "Withdraw", params: 4, optional: 0 },
+    Opcode { code: DEPOSIT, name: "Deposit", params: 4, optional: 4 },
+    Opcode { code: BOUNTY, name: "Bounty", params: 1, optional: 0 },
+    Opcode { code: COLLECT_ENERGY, name: "CollectEnergy", params: 0, optional: 0 },
+    Opcode { code: LIST_CARD_IN_MARKET, name: "ListCardInMarket", params: 2, optional: 0 },
+    Opcode { code: BID_CARD, name: "BidCard", params: 2, optional: 0 },
+    Opcode { code: SELL_CARD, name: "SellCard", params: 1, optional: 0 },
+    Opcode { code: GIFT_ENERGY, name: "GiftEnergy", params: 3, optional: 0 },
+    Opcode { code: BUY_ENERGY_PACK, name: "BuyEnergyPack", params: 1, optional: 0 },
+    Opcode { code: CLAIM_WITHDRAW, name: "ClaimWithdraw", params: 0, optional: 0 },
+    Opcode { code: CANCEL_WITHDRAW, name: "CancelWithdraw", params: 0, optional: 0 },
+    Opcode { code: SET_WITHDRAW_LIMITS, name: "SetWithdrawLimits", params: 4, optional: 0 },
+    Opcode { code: SET_PAUSE, name: "SetPause", params: 1, optional: 0 },
+    Opcode { code: ADD_ADMIN, name: "AddAdmin", params: 3, optional: 0 },
+    Opcode { code: REMOVE_ADMIN, name: "RemoveAdmin", params: 2, optional: 0 },
+    Opcode { code: SET_CONFIG, name: "SetConfig", params: 2, optional: 0 },
+    Opcode { code: REFUND_DEPOSIT, name: "RefundDeposit", params: 2, optional: 3 },
+];
+
+impl Opcode {
+    pub fn lookup(code: u64) -> Option<&'static Opcode> {
+        OPCODES.iter().find(|x| x.code == code)
+    }
+
+    pub fn header(&self, nonce: u64) -> u64 {
+        (nonce << 16) | self.code
+    }
+}
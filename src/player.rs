@@ -1,19 +1,23 @@
 use crate::card::{Card, DEFAULT_CARDS};
-use crate::config::{CONFIG, COST_INCREASE_ROUND, COST_INCREASE_ROUND_INITIAL};
-use crate::config::{default_local, random_modifier, INITIAL_ENERGY};
+use crate::config::{CONFIG, COST_INCREASE_ROUND_INITIAL};
+use crate::config::{default_local, random_modifier};
 use crate::error::*;
+use crate::ledger;
 use crate::object::Object;
 use crate::Player;
 use crate::StorageData;
-use crate::MERKLE_MAP;
-use serde::Serialize;
+use crate::storage;
+use crate::storage::TryFromData;
+use serde::ser::SerializeSeq;
+use serde::{Serialize, Serializer};
+use std::collections::BTreeMap;
 use std::slice::IterMut;
 use zkwasm_rest_abi::enforce;
-use zkwasm_rest_convention::IndexedObject;
 use zkwasm_rest_convention::Wrapped;
 use zkwasm_rest_convention::BidObject;
 use zkwasm_rest_convention::WithBalance;
 use crate::card::MarketCard;
+use crate::events::emit_level_up;
 
 #[derive(Clone, Debug, Serialize)]
 pub struct Attributes(pub Vec<i64>);
@@ -45,6 +49,31 @@ pub struct PlayerData {
     pub objects: Vec<Object>,
     pub local: Attributes,
     pub cards: Vec<Card>,
+    pub card_rolls: u16, // free card rolls granted on level up, used by InstallCard before paying
+    pub last_regen_stamp: u32, // counter up to which passive energy regeneration was applied
+    pub gift_info: u64, // gift window << 32 | energy received << 16 | energy sent
+    pub tokens: BTreeMap<u64, u64>, // balances of tokens other than 0, token 0 is the treasure in local
+    pub withdraw_info: u64, // withdraw window start counter << 32 | amount withdrawn in the window
+    pub pending_withdraw: Option<PendingWithdraw>,
+    #[serde(skip)]
+    pub level_ups: Vec<LevelUp>, // not stored, emitted as events when the player is stored
+}
+
+/// A withdrawal taken from the balance and the pool, settled once the counter reaches mature
+#[derive(Clone, Debug, Serialize)]
+pub struct PendingWithdraw {
+    pub token: u64,
+    pub data: [u64; 3],
+    pub mature: u64,
+}
+
+/// Rewards granted when the player reached level
+#[derive(Clone, Debug)]
+pub struct LevelUp {
+    pub level: u16,
+    pub energy: u16,
+    pub treasure: u64,
+    pub card_rolls: u16,
 }
 
 impl Default for PlayerData {
@@ -53,7 +82,7 @@ impl Default for PlayerData {
             level: 1,
             exp: 0,
             last_check_point: 0,
-            energy: INITIAL_ENERGY,
+            energy: CONFIG.initial_energy(),
             cost_info: COST_INCREASE_ROUND_INITIAL,
             current_cost: 0,
             redeem_info: [0; 8],
@@ -61,6 +90,13 @@ impl Default for PlayerData {
             objects: vec![],
             local: Attributes::default_local(),
             cards: DEFAULT_CARDS.clone(),
+            card_rolls: 0,
+            last_regen_stamp: 0,
+            gift_info: 0,
+            tokens: BTreeMap::new(),
+            withdraw_info: 0,
+            pending_withdraw: None,
+            level_ups: vec![],
         }
     }
 }
@@ -73,9 +109,11 @@ impl Attributes {
 
 impl WithBalance for PlayerData {
     fn cost_balance(&mut self, b: u64) -> Result<(), u32> {
+        // a cost above i64::MAX, such as a BidCard price, is more than any balance
+        let b = i64::try_from(b).map_err(|_| ERROR_NOT_ENOUGH_BALANCE)?;
         if let Some(treasure) = self.local.0.last_mut() {
-            if *treasure >= b as i64 {
-                *treasure -= b as i64;
+            if *treasure >= b {
+                *treasure -= b;
                 Ok(())
             } else {
                 Err(ERROR_NOT_ENOUGH_BALANCE)
@@ -105,9 +143,9 @@ impl PlayerData {
         self.cost_balance(self.current_cost as u64 + base)?;
         self.cost_info -= 1;
         if self.cost_info == 0 {
-            self.cost_info = COST_INCREASE_ROUND;
+            self.cost_info = CONFIG.cost_increase_round();
             if self.current_cost != 0 {
-                self.current_cost = self.current_cost * 2
+                self.current_cost = self.current_cost.saturating_mul(2)
             } else {
                 self.current_cost = 1;
             }
@@ -118,7 +156,7 @@ impl PlayerData {
         } else {
             self.energy += 20;
         }
-        self.inc_exp((self.current_cost + 1).ilog2() as u16);
+        self.inc_exp(self.current_cost.saturating_add(1).ilog2() as u16);
         Ok(())
     }
 
@@ -132,23 +170,220 @@ impl PlayerData {
         }
     }
 
+    pub fn get_token_balance(&self, token: u64) -> u64 {
+        if token == 0 {
+            self.get_balance()
+        } else {
+            self.tokens.get(&token).copied().unwrap_or(0)
+        }
+    }
+
+    pub fn inc_token_balance(&mut self, token: u64, amount: u64) {
+        if token == 0 {
+            self.inc_balance(amount)
+        } else {
+            let balance = self.tokens.entry(token).or_insert(0);
+            *balance = balance.saturating_add(amount);
+        }
+    }
+
+    /// Same as inc_token_balance but fails instead of overflowing the balance
+    pub fn checked_inc_token_balance(&mut self, token: u64, amount: u64) -> Result<(), u32> {
+        let balance = self.get_token_balance(token).checked_add(amount).ok_or(ERROR_BALANCE_OVERFLOW)?;
+        if token == 0 && balance > i64::MAX as u64 {
+            return Err(ERROR_BALANCE_OVERFLOW);
+        }
+        self.inc_token_balance(token, amount);
+        Ok(())
+    }
+
+    pub fn cost_token_balance(&mut self, token: u64, amount: u64) -> Result<(), u32> {
+        if token == 0 {
+            self.cost_balance(amount)
+        } else {
+            let balance = self.tokens.get(&token).copied().unwrap_or(0);
+            if balance < amount {
+                Err(ERROR_NOT_ENOUGH_BALANCE)
+            } else if balance == amount {
+                self.tokens.remove(&token);
+                Ok(())
+            } else {
+                self.tokens.insert(token, balance - amount);
+                Ok(())
+            }
+        }
+    }
+
+    /// Counts amount against the player's withdraw limit of the window starting at window
+    pub fn track_withdraw(&mut self, window: u64, amount: u64, limit: u64) -> Result<(), u32> {
+        let window = window & 0xffffffff;
+        let withdrawn = if self.withdraw_info >> 32 == window {
+            self.withdraw_info & 0xffffffff
+        } else {
+            0
+        };
+        if withdrawn + amount > limit || withdrawn + amount > 0xffffffff {
+            Err(ERROR_WITHDRAW_LIMIT_EXCEEDED)
+        } else {
+            self.withdraw_info = (window << 32) | (withdrawn + amount);
+            Ok(())
+        }
+    }
+
     pub fn update_interest(&mut self, counter: u64) {
-        self.last_interest_stamp = (self.get_balance() << 32) + counter
+        self.last_interest_stamp = (self.get_balance().min(0xffffffff) << 32) + (counter & 0xffffffff)
     }
 
-    pub fn upgrade_object(&mut self, object_index: usize, index: usize) {
-        let object = self.objects.get_mut(object_index).unwrap();
-        enforce(object.attributes[0] < 128, "check attributes bound");
-        object.attributes[0] += 1;
-        object.attributes[index] += 1;
+    pub fn upgrade_object(&mut self, object_index: usize, index: usize) -> Result<(), u32> {
+        let object = self.objects.get_mut(object_index).ok_or(ERROR_INDEX_OUT_OF_BOUND)?;
+        if index == 0 || index >= object.attributes.len() {
+            Err(ERROR_INDEX_OUT_OF_BOUND)
+        } else if object.attributes[0] >= 128 {
+            Err(ERROR_OBJECT_AT_MAX_LEVEL)
+        } else {
+            object.attributes[0] += 1;
+            object.attributes[index] += 1;
+            Ok(())
+        }
     }
 
+    /// Checks that every modifier of an object refers to an existing card
+    pub fn check_modifiers(&self, modifiers: &[u8; 8]) -> Result<(), u32> {
+        if modifiers.iter().all(|m| (*m as usize) < self.cards.len()) {
+            Ok(())
+        } else {
+            Err(ERROR_CARD_INDEX_OUT_OF_RANGE)
+        }
+    }
+
+    /// Checks the modifiers an object is about to run with.
+    /// object_index is skipped when looking for shared cards so an object can keep its own cards on restart.
+    pub fn validate_modifiers(&self, object_index: usize, modifiers: &[u8; 8]) -> Result<(), u32> {
+        self.check_modifiers(modifiers)?;
+        if modifiers.iter().any(|m| self.cards[*m as usize].marketid != 0) {
+            return Err(ERROR_CARD_IS_LISTED);
+        }
+        if CONFIG.exclusive_cards() {
+            for (index, obj) in self.objects.iter().enumerate() {
+                if index != object_index && obj.cards.iter().any(|c| modifiers.contains(c)) {
+                    return Err(ERROR_CARD_SHARED_BY_OBJECTS);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds exp and levels up as many times as it covers, excess exp carries over
     pub fn inc_exp(&mut self, a: u16) {
-        self.exp += a;
-        if self.exp >= 100 + self.level * 10 {
+        let mut exp = self.exp as u64 + a as u64;
+        while self.level < u16::MAX {
+            let required = CONFIG.get_exp_to_level_up(self.level);
+            if exp < required {
+                break;
+            }
+            exp -= required;
             self.level += 1;
-            self.exp = 0;
+            self.grant_level_up();
         }
+        self.exp = exp.min(0xffff) as u16;
+    }
+
+    fn grant_level_up(&mut self) {
+        let reward = LevelUp {
+            level: self.level,
+            energy: CONFIG.get_level_up_energy(),
+            treasure: CONFIG.get_level_up_treasure(),
+            card_rolls: CONFIG.get_level_up_card_rolls(),
+        };
+        self.energy = self.energy.saturating_add(reward.energy);
+        self.inc_balance(reward.treasure);
+        ledger::mint(reward.treasure);
+        self.card_rolls = self.card_rolls.saturating_add(reward.card_rolls);
+        self.level_ups.push(reward);
+    }
+
+    /// Applies the passive energy regenerated since last_regen_stamp.
+    /// Regeneration only fills up to the level cap, energy from other sources may exceed it.
+    pub fn regen_energy(&mut self, counter: u64) {
+        let counter = counter & 0xffffffff;
+        let stamp = self.last_regen_stamp as u64;
+        let cap = CONFIG.get_max_energy(self.level);
+        if self.energy >= cap || counter < stamp {
+            self.last_regen_stamp = counter as u32;
+        } else {
+            let period = CONFIG.get_energy_regen_period();
+            let points = (counter - stamp) / period;
+            self.energy = (self.energy as u64 + points).min(cap as u64) as u16;
+            if self.energy == cap {
+                self.last_regen_stamp = counter as u32;
+            } else {
+                // keep the partial period for the next regeneration
+                self.last_regen_stamp = (stamp + points * period) as u32;
+            }
+        }
+    }
+
+    /// Adds energy without passing the 0xffff cap
+    pub fn add_energy(&mut self, amount: u64) -> Result<(), u32> {
+        let energy = self.energy as u64 + amount;
+        if energy > 0xffff {
+            Err(ERROR_ENERGY_CAP_EXCEEDED)
+        } else {
+            self.energy = energy as u16;
+            Ok(())
+        }
+    }
+
+    // energy (sent, received) in the gift window of counter
+    fn gift_usage(&self, counter: u64) -> (u16, u16) {
+        let window = counter / CONFIG.get_energy_gift_window();
+        if self.gift_info >> 32 == window & 0xffffffff {
+            ((self.gift_info & 0xffff) as u16, ((self.gift_info >> 16) & 0xffff) as u16)
+        } else {
+            (0, 0)
+        }
+    }
+
+    fn set_gift_usage(&mut self, counter: u64, sent: u16, received: u16) {
+        let window = (counter / CONFIG.get_energy_gift_window()) & 0xffffffff;
+        self.gift_info = (window << 32) | ((received as u64) << 16) | sent as u64;
+    }
+
+    /// Takes amount out of the sender's energy and its gift allowance.
+    /// Returns the energy left after the transfer tax.
+    pub fn send_energy(&mut self, amount: u16, counter: u64) -> Result<u16, u32> {
+        let (sent, received) = self.gift_usage(counter);
+        let (send_limit, _) = CONFIG.get_energy_gift_limits();
+        if sent as u64 + amount as u64 > send_limit as u64 {
+            return Err(ERROR_ENERGY_GIFT_LIMIT_EXCEEDED);
+        }
+        if self.energy < amount {
+            return Err(ERROR_NOT_ENOUGH_ENERGY);
+        }
+        self.energy -= amount;
+        self.set_gift_usage(counter, sent + amount, received);
+        Ok(amount - CONFIG.get_energy_gift_tax(amount))
+    }
+
+    pub fn receive_energy(&mut self, amount: u16, counter: u64) -> Result<(), u32> {
+        let (sent, received) = self.gift_usage(counter);
+        let (_, receive_limit) = CONFIG.get_energy_gift_limits();
+        if received as u64 + amount as u64 > receive_limit as u64 {
+            return Err(ERROR_ENERGY_GIFT_LIMIT_EXCEEDED);
+        }
+        self.add_energy(amount as u64)?;
+        self.set_gift_usage(counter, sent, received + amount);
+        Ok(())
+    }
+
+    pub fn buy_energy_pack(&mut self, packs: u64) -> Result<(), u32> {
+        let (size, price) = CONFIG.get_energy_pack();
+        let energy = (size as u64).saturating_mul(packs);
+        if self.energy as u64 + energy > 0xffff {
+            return Err(ERROR_ENERGY_CAP_EXCEEDED);
+        }
+        self.cost_balance(price.saturating_mul(packs))?;
+        self.add_energy(energy)
     }
 
     pub fn collect_energy(&mut self, counter: u64) -> Result <(), u32> {
@@ -167,20 +402,28 @@ impl PlayerData {
         }
     }
 
-    pub fn collect_interest(&mut self, counter: u64) -> Result <(), u32> {
-        let mut balance = self.last_interest_stamp >> 32;
-        let current_balance = self.get_balance();
-        if balance > current_balance {
-            balance = current_balance
-        }
+    /// Pays the interest accrued on the held balance out of reserve and charges the claim fee.
+    /// Returns the interest paid so the caller can take it from the reserve.
+    pub fn collect_interest(&mut self, counter: u64, reserve: u64) -> Result<u64, u32> {
+        let balance = (self.last_interest_stamp >> 32).min(self.get_balance());
         let timestamp = self.last_interest_stamp & 0xffffffff;
-        let delta = counter - timestamp;
-        let interest = ((self.level as u64) * balance * delta / (10000 * 17280)) as i64;
-        self.last_interest_stamp = (self.get_balance() << 32) + counter;
-
-        // TODO: cost balance needs check interest bound
-        self.inc_balance(interest as u64 - 100);
-        Ok(())
+        let delta = (counter & 0xffffffff).saturating_sub(timestamp);
+        if delta < CONFIG.get_interest_holding_period() {
+            return Err(ERROR_INTEREST_NOT_MATURED);
+        }
+        if reserve == 0 {
+            return Err(ERROR_INTEREST_RESERVE_EXHAUSTED);
+        }
+        let rate = CONFIG.get_interest_rate(self.level) as u128;
+        let interest = (rate * balance as u128 * delta as u128 / (10000 * 17280)).min(reserve as u128) as u64;
+        let fee = CONFIG.get_interest_fee();
+        if interest <= fee {
+            return Err(ERROR_INTEREST_BELOW_FEE);
+        }
+        // the fee is taken from the interest, so the balance does not need to cover it
+        self.inc_balance(interest - fee);
+        self.update_interest(counter);
+        Ok(interest)
     }
 
     fn card_used(&self, card_index: usize) -> bool {
@@ -242,7 +485,7 @@ impl PlayerData {
             let marketid = card.marketid;
             card.marketid = 0;
             if marketid != 0 {
-                let wrapped_market_card = MarketCard::get_object(marketid).unwrap();
+                let wrapped_market_card = MarketCard::load(marketid).ok_or(ERROR_MARKET_NOT_EXIST)?;
                 if let Some(b) = wrapped_market_card.data.0.get_bidder() {
                     self.inc_balance(b.bidprice);
                     self.remove_card(card_index);
@@ -259,7 +502,12 @@ impl PlayerData {
     }
 
     pub fn apply_object_card(&mut self, object_index: usize, counter: u64) -> Option<usize> {
-        let object = self.objects[object_index].clone();
+        let object = self.objects.get(object_index)?.clone();
+        if self.check_modifiers(&object.cards).is_err() {
+            // a card was removed while the object was running, stop until it is restarted
+            self.objects[object_index].halt(counter);
+            return None;
+        }
         let mut speed = (object.attributes[1] + 1).ilog2() as u64;
         if speed > 9 { speed = 9 };
         let current_index = object.get_modifier_index() as usize;
@@ -285,7 +533,7 @@ impl PlayerData {
                 object.start_new_modifier(next_index, counter);
                 Some(duration as usize)
             } else {
-                object.halt();
+                object.halt(counter);
                 None
             }
         }
@@ -296,8 +544,12 @@ impl PlayerData {
         object_index: usize,
         data: [u8; 8],
         counter: u64,
-    ) -> Option<usize> {
-        let object = self.objects.get_mut(object_index).unwrap();
+    ) -> Result<Option<usize>, u32> {
+        if object_index >= self.objects.len() {
+            return Err(ERROR_INDEX_OUT_OF_BOUND);
+        }
+        self.validate_modifiers(object_index, &data)?;
+        let object = self.objects.get_mut(object_index).ok_or(ERROR_INDEX_OUT_OF_BOUND)?;
         let halted = object.is_halted();
         if halted {
             // modify object with new modifiers
@@ -306,11 +558,11 @@ impl PlayerData {
             let duration = self.cards[object.cards[modifier_index as usize] as usize].duration;
             object.restart(counter);
             //zkwasm_rust_sdk::dbg!("object restarted\n");
-            Some(duration as usize)
+            Ok(Some(duration as usize))
         } else {
             object.reset_modifier(data);
             object.reset_halt_bit_to_restart();
-            None
+            Ok(None)
         }
     }
     pub fn apply_modifier(&mut self, m: &Card, o: &Object) -> bool {
@@ -336,30 +588,152 @@ impl PlayerData {
     }
 }
 
-impl StorageData for PlayerData {
-    fn from_data(u64data: &mut IterMut<u64>) -> Self {
-        let player_info = *u64data.next().unwrap();
-        let cost_info = *u64data.next().unwrap();
-        let redeem_info = *u64data.next().unwrap();
-        let last_interest_stamp = *u64data.next().unwrap();
-        let objects_size = *u64data.next().unwrap();
-        let mut objects = Vec::with_capacity(objects_size as usize);
+/* Player record schema
+ *
+ * The first word of a stored player is PLAYER_DATA_TAG | version. Records written
+ * before the header existed start directly with player_info, whose top 16 bits hold
+ * the level and can never be 0xffff, so they are read as version 0.
+ *
+ * Each migration upgrades the body (the words after the header) from version i to i + 1.
+ */
+const PLAYER_DATA_TAG: u64 = 0xffff << 48;
+pub const PLAYER_DATA_VERSION: u64 = 6;
+
+type Migration = fn(Vec<u64>) -> Result<Vec<u64>, u32>;
+
+const PLAYER_DATA_MIGRATIONS: [Migration; PLAYER_DATA_VERSION as usize] = [
+    migrate_v0_to_v1,
+    migrate_v1_to_v2,
+    migrate_v2_to_v3,
+    migrate_v3_to_v4,
+    migrate_v4_to_v5,
+    migrate_v5_to_v6,
+];
+
+// v1 only introduced the schema header, the body layout is unchanged
+fn migrate_v0_to_v1(data: Vec<u64>) -> Result<Vec<u64>, u32> {
+    Ok(data)
+}
+
+// v2 added card_rolls after last_interest_stamp
+fn migrate_v1_to_v2(mut data: Vec<u64>) -> Result<Vec<u64>, u32> {
+    if data.len() < 4 {
+        return Err(ERROR_INVALID_STORAGE_DATA);
+    }
+    data.insert(4, 0);
+    Ok(data)
+}
+
+// v3 added last_regen_stamp after card_rolls, migrated players regenerate up to their cap once
+fn migrate_v2_to_v3(mut data: Vec<u64>) -> Result<Vec<u64>, u32> {
+    if data.len() < 5 {
+        return Err(ERROR_INVALID_STORAGE_DATA);
+    }
+    data.insert(5, 0);
+    Ok(data)
+}
+
+// v4 added gift_info after last_regen_stamp
+fn migrate_v3_to_v4(mut data: Vec<u64>) -> Result<Vec<u64>, u32> {
+    if data.len() < 6 {
+        return Err(ERROR_INVALID_STORAGE_DATA);
+    }
+    data.insert(6, 0);
+    Ok(data)
+}
+
+// v5 added the token balances after gift_info, stored as a size followed by (token, balance) pairs
+fn migrate_v4_to_v5(mut data: Vec<u64>) -> Result<Vec<u64>, u32> {
+    if data.len() < 7 {
+        return Err(ERROR_INVALID_STORAGE_DATA);
+    }
+    data.insert(7, 0);
+    Ok(data)
+}
+
+// v6 added withdraw_info and the pending withdrawal after the token balances
+fn migrate_v5_to_v6(mut data: Vec<u64>) -> Result<Vec<u64>, u32> {
+    let tokens_size = *data.get(7).ok_or(ERROR_INVALID_STORAGE_DATA)? as usize;
+    let at = tokens_size.checked_mul(2).and_then(|x| x.checked_add(8)).ok_or(ERROR_INVALID_STORAGE_DATA)?;
+    if data.len() < at {
+        return Err(ERROR_INVALID_STORAGE_DATA);
+    }
+    data.splice(at..at, [0, 0]);
+    Ok(data)
+}
+
+fn next_word(u64data: &mut IterMut<u64>) -> Result<u64, u32> {
+    u64data.next().map(|x| *x).ok_or(ERROR_INVALID_STORAGE_DATA)
+}
+
+impl TryFromData for PlayerData {
+    fn try_from_data(u64data: &mut IterMut<u64>) -> Result<Self, u32> {
+        let head = *u64data.next().ok_or(ERROR_INVALID_STORAGE_DATA)?;
+        let (version, mut body) = if head & PLAYER_DATA_TAG == PLAYER_DATA_TAG {
+            (head & !PLAYER_DATA_TAG, u64data.map(|x| *x).collect::<Vec<_>>())
+        } else {
+            (0, std::iter::once(head).chain(u64data.map(|x| *x)).collect::<Vec<_>>())
+        };
+        if version > PLAYER_DATA_VERSION {
+            return Err(ERROR_INVALID_STORAGE_DATA);
+        }
+        for migration in PLAYER_DATA_MIGRATIONS[version as usize..].iter() {
+            body = migration(body)?;
+        }
+        let mut body = body.iter_mut();
+        let player = Self::decode_body(&mut body)?;
+        if body.len() != 0 {
+            Err(ERROR_INVALID_STORAGE_DATA)
+        } else {
+            Ok(player)
+        }
+    }
+}
+
+impl PlayerData {
+    fn decode_body(u64data: &mut IterMut<u64>) -> Result<Self, u32> {
+        let player_info = next_word(u64data)?;
+        let cost_info = next_word(u64data)?;
+        let redeem_info = next_word(u64data)?;
+        let last_interest_stamp = next_word(u64data)?;
+        let card_rolls = next_word(u64data)?;
+        let last_regen_stamp = next_word(u64data)?;
+        let gift_info = next_word(u64data)?;
+        let tokens_size = next_word(u64data)?;
+        let mut tokens = BTreeMap::new();
+        for _ in 0..tokens_size {
+            let token = next_word(u64data)?;
+            tokens.insert(token, next_word(u64data)?);
+        }
+        let withdraw_info = next_word(u64data)?;
+        // 0 when no withdrawal is queued, otherwise 1 followed by token, data and mature
+        let pending_withdraw = match next_word(u64data)? {
+            0 => None,
+            1 => Some(PendingWithdraw {
+                token: next_word(u64data)?,
+                data: [next_word(u64data)?, next_word(u64data)?, next_word(u64data)?],
+                mature: next_word(u64data)?,
+            }),
+            _ => return Err(ERROR_INVALID_STORAGE_DATA),
+        };
+        let objects_size = next_word(u64data)?;
+        let mut objects = vec![];
         for _ in 0..objects_size {
-            objects.push(Object::from_data(u64data));
+            objects.push(Object::try_from_data(u64data)?);
         }
 
-        let local_size = *u64data.next().unwrap();
-        let mut local = Vec::with_capacity(local_size as usize);
+        let local_size = next_word(u64data)?;
+        let mut local = vec![];
         for _ in 0..local_size {
-            local.push(*u64data.next().unwrap() as i64);
+            local.push(next_word(u64data)? as i64);
         }
 
-        let card_size = *u64data.next().unwrap();
-        let mut cards = Vec::with_capacity(card_size as usize);
+        let card_size = next_word(u64data)?;
+        let mut cards = vec![];
         for _ in 0..card_size {
-            cards.push(Card::from_data(u64data));
+            cards.push(Card::try_from_data(u64data)?);
         }
-        PlayerData {
+        Ok(PlayerData {
             level: ((player_info >> 48) & 0xffff) as u16,
             exp: ((player_info >> 32) & 0xffff) as u16,
             last_check_point : (player_info & 0xffffffff) as u32,
@@ -371,9 +745,25 @@ impl StorageData for PlayerData {
             objects,
             local: Attributes(local),
             cards,
-        }
+            card_rolls: card_rolls as u16,
+            last_regen_stamp: last_regen_stamp as u32,
+            gift_info,
+            tokens,
+            withdraw_info,
+            pending_withdraw,
+            level_ups: vec![],
+        })
+    }
+}
+
+impl StorageData for PlayerData {
+    fn from_data(u64data: &mut IterMut<u64>) -> Self {
+        let player = PlayerData::try_from_data(u64data);
+        enforce(player.is_ok(), "check player data");
+        player.unwrap()
     }
     fn to_data(&self, data: &mut Vec<u64>) {
+        data.push(PLAYER_DATA_TAG | PLAYER_DATA_VERSION);
         data.push(
             ((self.level as u64) << 48)
                 + ((self.exp as u64) << 32)
@@ -386,6 +776,24 @@ impl StorageData for PlayerData {
         );
         data.push(u64::from_le_bytes(self.redeem_info));
         data.push(self.last_interest_stamp);
+        data.push(self.card_rolls as u64);
+        data.push(self.last_regen_stamp as u64);
+        data.push(self.gift_info);
+        data.push(self.tokens.len() as u64);
+        for (token, balance) in self.tokens.iter() {
+            data.push(*token);
+            data.push(*balance);
+        }
+        data.push(self.withdraw_info);
+        match &self.pending_withdraw {
+            None => data.push(0),
+            Some(w) => {
+                data.push(1);
+                data.push(w.token);
+                data.extend_from_slice(&w.data);
+                data.push(w.mature);
+            }
+        }
         data.push(self.objects.len() as u64);
         for c in self.objects.iter() {
             c.to_data(data);
@@ -401,28 +809,109 @@ impl StorageData for PlayerData {
     }
 }
 
-pub type AutomataPlayer = Player<PlayerData>;
+// Custom serializer for `[u64; 2]` as a [String; 2].
+fn serialize_pid_as_string<S>(value: &[u64; 2], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let mut seq = serializer.serialize_seq(Some(value.len()))?;
+    for e in value.iter() {
+        seq.serialize_element(&e.to_string())?;
+    }
+    seq.end()
+}
 
-pub trait Owner: Sized {
-    fn store(&self);
-    fn new(pkey: &[u64; 4]) -> Self;
-    fn get(pkey: &[u64; 4]) -> Option<Self>;
+/// Player record stored at Player::to_key(pid) as the nonce followed by PlayerData.
+/// It is persisted through crate::storage so it also works against the native backend.
+#[derive(Debug, Serialize)]
+pub struct AutomataPlayer {
+    #[serde(serialize_with = "serialize_pid_as_string")]
+    pub player_id: [u64; 2],
+    pub nonce: u64,
+    pub data: PlayerData,
 }
 
-impl Owner for AutomataPlayer {
-    fn store(&self) {
-        //zkwasm_rust_sdk::dbg!("store player\n");
-        let mut data = Vec::new();
-        self.data.to_data(&mut data);
-        let kvpair = unsafe { &mut MERKLE_MAP };
-        kvpair.set(&Self::to_key(&self.player_id), data.as_slice());
-        //zkwasm_rust_sdk::dbg!("end store player\n");
+impl AutomataPlayer {
+    pub fn pkey_to_pid(pkey: &[u64; 4]) -> [u64; 2] {
+        Player::<PlayerData>::pkey_to_pid(pkey)
+    }
+
+    pub fn to_key(pid: &[u64; 2]) -> [u64; 4] {
+        Player::<PlayerData>::to_key(pid)
     }
-    fn new(pkey: &[u64; 4]) -> Self {
+
+    pub fn new_from_pid(pid: [u64; 2]) -> Self {
+        AutomataPlayer {
+            player_id: pid,
+            nonce: 0,
+            data: PlayerData::default(),
+        }
+    }
+
+    pub fn new(pkey: &[u64; 4]) -> Self {
         Self::new_from_pid(Self::pkey_to_pid(pkey))
     }
 
-    fn get(pkey: &[u64; 4]) -> Option<Self> {
+    pub fn get(pkey: &[u64; 4]) -> Option<Self> {
         Self::get_from_pid(&Self::pkey_to_pid(pkey))
     }
+
+    pub fn get_from_pid(pid: &[u64; 2]) -> Option<Self> {
+        let player = Self::load(pid);
+        enforce(player.is_ok(), "check player data");
+        player.unwrap()
+    }
+
+    /// Same as get_from_pid but returns an error instead of halting on corrupted records
+    pub fn load(pid: &[u64; 2]) -> Result<Option<Self>, u32> {
+        let mut data = storage::get(&Self::to_key(pid));
+        if data.is_empty() {
+            Ok(None)
+        } else {
+            let mut u64data = data.iter_mut();
+            let nonce = *u64data.next().unwrap();
+            let data = PlayerData::try_from_data(&mut u64data)?;
+            Ok(Some(AutomataPlayer {
+                player_id: *pid,
+                nonce,
+                data,
+            }))
+        }
+    }
+
+    pub fn store(&self) {
+        let mut data = vec![self.nonce];
+        self.data.to_data(&mut data);
+        storage::set(&Self::to_key(&self.player_id), data.as_slice());
+        for reward in self.data.level_ups.iter() {
+            emit_level_up(&self.player_id, reward);
+        }
+    }
+
+    /// Runs f on the record as a Player of zkwasm_rest_abi, for the zkwasm_rest_convention
+    /// helpers that take one
+    pub fn as_player<R>(&mut self, f: impl FnOnce(&mut Player<PlayerData>) -> R) -> R {
+        let mut player = Player {
+            player_id: self.player_id,
+            nonce: self.nonce,
+            data: std::mem::take(&mut self.data),
+        };
+        let result = f(&mut player);
+        self.nonce = player.nonce;
+        self.data = player.data;
+        result
+    }
+
+    pub fn from_player(player: Player<PlayerData>) -> Self {
+        AutomataPlayer {
+            player_id: player.player_id,
+            nonce: player.nonce,
+            data: player.data,
+        }
+    }
+
+    pub fn check_and_inc_nonce(&mut self, nonce: u64) {
+        enforce(self.nonce == nonce, "check nonce");
+        self.nonce += 1;
+    }
 }
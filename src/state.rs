@@ -1,19 +1,27 @@
-use crate::config::ADMIN_PUBKEY;
-use crate::config::CONFIG;
+use crate::admin::{self, ROLE_ALL, ROLE_CONFIG_MANAGER, ROLE_DEPOSITOR, ROLE_SUPERADMIN, ROLE_TICKER};
+use crate::config;
+use crate::config::{CONFIG, PARAMS};
+use crate::encode;
 use crate::error::*;
-use crate::events::Event;
+use crate::deposit::{self, PendingDeposit, DEPOSIT_CREATE, DEPOSIT_PENDING};
+use crate::events::{self, emit_config_change, emit_deposit, Event};
+use crate::events::{DEPOSIT_CLAIMED, DEPOSIT_CREATED, DEPOSIT_CREDITED, DEPOSIT_PENDED};
+use crate::ledger::{self, Ledger, PoolLedger};
 use crate::object::Object;
+use crate::opcode::*;
 use crate::player::AutomataPlayer;
-use crate::player::Owner;
 use crate::card::Card;
 use crate::card::MarketCard;
 use std::cell::RefCell;
 use serde::Serialize;
 use zkwasm_rest_abi::StorageData;
 use zkwasm_rest_abi::WithdrawInfo;
+#[cfg(target_arch = "wasm32")]
 use zkwasm_rest_abi::MERKLE_MAP;
+use crate::storage;
 use zkwasm_rest_convention::EventQueue;
-use crate::player::PlayerData;
+use zkwasm_rest_convention::EventHandler;
+use crate::player::{PendingWithdraw, PlayerData};
 use zkwasm_rest_convention::MarketInfo;
 use zkwasm_rest_convention::WithBalance;
 use zkwasm_rest_convention::SettlementInfo;
@@ -56,7 +64,58 @@ pub enum Command {
     Bounty(Bounty),
     InstallPlayer,
     CollectEnergy(CollectEnergy),
+    GiftEnergy(GiftEnergy),
+    BuyEnergyPack(BuyEnergyPack),
+    ClaimWithdraw(ClaimWithdraw),
+    CancelWithdraw(CancelWithdraw),
+    SetWithdrawLimits(SetWithdrawLimits),
+    SetPause(SetPause),
+    AddAdmin(AddAdmin),
+    RemoveAdmin(RemoveAdmin),
+    SetConfig(SetConfig),
+    RefundDeposit(RefundDeposit),
     Tick,
+    Invalid(u32), // decode error of a malformed transaction
+}
+
+/* Pause flags
+ *
+ * SetPause stores a mask of the classes below in State. PAUSE_ALL stops every player
+ * command, the others only stop their class. Admin commands are never paused.
+ */
+pub const PAUSE_ALL: u64 = 1;
+pub const PAUSE_MARKET: u64 = 1 << 1; // ListCardInMarket, BidCard, SellCard
+pub const PAUSE_WITHDRAW: u64 = 1 << 2; // Withdraw, ClaimWithdraw
+pub const PAUSE_CRAFTING: u64 = 1 << 3; // InstallObject, RestartObject, UpgradeObject, InstallCard
+const PAUSE_NAMES: [(u64, &str); 4] = [
+    (PAUSE_ALL, "all"),
+    (PAUSE_MARKET, "market"),
+    (PAUSE_WITHDRAW, "withdraw"),
+    (PAUSE_CRAFTING, "crafting"),
+];
+
+impl Command {
+    // pause flags that stop this command, 0 for admin commands
+    fn pause_flags(&self) -> u64 {
+        match self {
+            Command::Tick
+            | Command::Deposit(_)
+            | Command::SetWithdrawLimits(_)
+            | Command::SetPause(_)
+            | Command::AddAdmin(_)
+            | Command::RemoveAdmin(_)
+            | Command::SetConfig(_)
+            | Command::RefundDeposit(_)
+            | Command::Invalid(_) => 0,
+            Command::ListCardInMarket(_) | Command::BidCard(_) | Command::SellCard(_) => PAUSE_ALL | PAUSE_MARKET,
+            Command::Withdraw(_) | Command::ClaimWithdraw(_) => PAUSE_ALL | PAUSE_WITHDRAW,
+            Command::InstallObject(_)
+            | Command::RestartObject(_)
+            | Command::UpgradeObject(_)
+            | Command::InstallCard(_) => PAUSE_ALL | PAUSE_CRAFTING,
+            _ => PAUSE_ALL,
+        }
+    }
 }
 
 trait CommandHandler {
@@ -71,13 +130,13 @@ pub struct UpgradeObject {
 
 impl CommandHandler for UpgradeObject {
     fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
-        let mut player = AutomataPlayer::get_from_pid(pid);
+        let mut player = AutomataPlayer::load(pid)?;
         match player.as_mut() {
-            None => Err(ERROR_PLAYER_ALREADY_EXIST),
+            None => Err(ERROR_PLAYER_NOT_EXIST),
             Some(player) => {
                 player.check_and_inc_nonce(nonce);
+                player.data.upgrade_object(self.object_index, self.feature_index)?;
                 player.data.pay_cost(0)?;
-                player.data.upgrade_object(self.object_index, self.feature_index);
                 player.store();
                 Ok(())
             }
@@ -93,19 +152,21 @@ pub struct InstallObject {
 
 impl CommandHandler for InstallObject {
     fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
-        let mut player = AutomataPlayer::get_from_pid(pid);
+        let mut player = AutomataPlayer::load(pid)?;
         match player.as_mut() {
             None => Err(ERROR_PLAYER_NOT_EXIST),
             Some(player) => {
                 player.check_and_inc_nonce(nonce);
                 let objindex = player.data.objects.len();
-                enforce(objindex == self.object_index, "check object index");
                 let level = player.data.level as usize;
-                if objindex > (level + 1) / 2 {
+                if objindex != self.object_index {
+                    Err(ERROR_INDEX_OUT_OF_BOUND)
+                } else if objindex > (level + 1) / 2 {
                     Err(ERROR_NOT_ENOUGH_LEVEL)
                 } else if objindex > 24 {
                     Err(ERROR_INDEX_OUT_OF_BOUND)
                 } else {
+                    player.data.validate_modifiers(self.object_index, &self.modifiers)?;
                     player.data.pay_cost(1000)?;
                     let cards = self.modifiers;
                     let mut object = Object::new(cards);
@@ -134,19 +195,26 @@ pub struct RestartObject {
 
 impl CommandHandler for RestartObject {
     fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
-        let mut player = AutomataPlayer::get_from_pid(pid);
+        let mut player = AutomataPlayer::load(pid)?;
         match player.as_mut() {
-            None => Err(ERROR_PLAYER_ALREADY_EXIST),
+            None => Err(ERROR_PLAYER_NOT_EXIST),
             Some(player) => {
                 player.check_and_inc_nonce(nonce);
-                player.data.pay_cost(0)?;
                 let counter = STATE.0.borrow().queue.counter;
-                if let Some(delay) = player.data.restart_object_card(
+                let halted_at = player.data.objects.get(self.object_index).map_or(0, |o| o.get_counter());
+                let restarted = player.data.restart_object_card(
                     self.object_index,
                     self.modifiers,
                     counter,
-                ) {
-                    STATE.0.borrow_mut().queue.insert(Event {
+                )?;
+                player.data.pay_cost(0)?;
+                if let Some(delay) = restarted {
+                    let mut state = STATE.0.borrow_mut();
+                    // objects halted before counters_since were never counted
+                    if halted_at >= state.counters_since {
+                        state.halted_objects = state.halted_objects.saturating_sub(1);
+                    }
+                    state.queue.insert(Event {
                         object_index: self.object_index,
                         owner: *pid,
                         delta: delay,
@@ -166,7 +234,7 @@ pub struct InstallCard {
 
 impl CommandHandler for InstallCard {
     fn handle(&self, pid: &[u64; 2], nonce: u64, rand: &[u64; 4]) -> Result<(), u32> {
-        let mut player = AutomataPlayer::get_from_pid(pid);
+        let mut player = AutomataPlayer::load(pid)?;
         match player.as_mut() {
             None => Err(ERROR_PLAYER_NOT_EXIST),
             Some(player) => {
@@ -175,7 +243,11 @@ impl CommandHandler for InstallCard {
                 if player.data.cards.len() >= 4 * level + 4 {
                     Err(ERROR_NOT_ENOUGH_LEVEL)
                 } else {
-                    player.data.pay_cost(0)?;
+                    if player.data.card_rolls > 0 {
+                        player.data.card_rolls -= 1;
+                    } else {
+                        player.data.pay_cost(0)?;
+                    }
                     player.data.generate_card(rand);
                     player.store();
                     Ok(())
@@ -193,7 +265,7 @@ pub struct ListCardInMarket {
 
 impl CommandHandler for ListCardInMarket {
     fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
-        let mut player = AutomataPlayer::get_from_pid(pid);
+        let mut player = AutomataPlayer::load(pid)?;
         match player.as_mut() {
             None => Err(ERROR_PLAYER_NOT_EXIST),
             Some(player) => {
@@ -203,9 +275,10 @@ impl CommandHandler for ListCardInMarket {
                 player.data.pay_cost(0)?;
                 let marketcard = MarketCard::new_object(marketcard, id);
                 player.store();
-                marketcard.store();
+                MarketCard::save(&marketcard);
                 let mut state = STATE.0.borrow_mut();
                 state.market_id += 1;
+                state.active_listings += 1;
                 state.event_id += 1;
                 MarketCard::emit_event(state.event_id, &marketcard.data);
                 Ok(())
@@ -223,7 +296,7 @@ pub struct SellCard {
 
 impl CommandHandler for SellCard {
     fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
-        let mut player = AutomataPlayer::get_from_pid(pid);
+        let mut player = AutomataPlayer::load(pid)?;
         let mut state = STATE.0.borrow_mut();
         let counter = state.queue.counter;
         match player.as_mut() {
@@ -233,19 +306,20 @@ impl CommandHandler for SellCard {
                 let mut marketcard = player.data.sell_card(self.card_index)?; 
                 // Shold not error from this point
                 if let Some(b) = marketcard.data.0.get_bidder() {
-                    let mut bidder = AutomataPlayer::get_from_pid(&b.bidder).unwrap();
+                    let mut bidder = AutomataPlayer::load(&b.bidder)?.ok_or(ERROR_PLAYER_NOT_EXIST)?;
                     marketcard.data.0.object.marketid = 0;
-                    marketcard.store();
+                    MarketCard::save(&marketcard);
                     bidder.data.cards.push(marketcard.data.0.object.clone());
                     bidder.store();
                 }
                 //marketcard.data.0.set_bidder(None);
                 marketcard.data.0.settleinfo = 2;
-                marketcard.store();
+                MarketCard::save(&marketcard);
                 player.data.update_interest(counter);
                 player.store();
                 MarketCard::emit_event(state.event_id, &marketcard.data);
                 state.event_id += 1;
+                state.active_listings = state.active_listings.saturating_sub(1);
                 Ok(())
             }
         }
@@ -260,18 +334,20 @@ pub struct BidCard {
 
 impl CommandHandler for BidCard {
     fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
-        let mut player = AutomataPlayer::get_from_pid(pid);
+        let mut player = AutomataPlayer::load(pid)?;
         let mut state = STATE.0.borrow_mut();
         let counter = state.queue.counter;
         match player.as_mut() {
             None => Err(ERROR_PLAYER_NOT_EXIST),
             Some(player) => {
                 player.check_and_inc_nonce(nonce);
-                let mut marketcard = MarketCard::get_object(self.marketindex).unwrap();
-                if marketcard.data.0.askprice <= self.price { // direct get the card
+                let mut marketcard = MarketCard::load(self.marketindex).ok_or(ERROR_MARKET_NOT_EXIST)?;
+                if marketcard.data.0.object.marketid == 0 { // already settled
+                    Err(ERROR_CARD_IS_IN_USE)
+                } else if marketcard.data.0.askprice <= self.price { // direct get the card
                     marketcard.data.0.settleinfo = 2;
                     marketcard.data.0.object.marketid = 0;
-                    let prev_bidder = marketcard.data.0.replace_bidder(player, self.price)?;
+                    let prev_bidder = marketcard.data.replace_bidder(player, self.price)?;
                     prev_bidder.map(|mut x| {
                         x.data.update_interest(counter);
                         x.store();
@@ -279,20 +355,24 @@ impl CommandHandler for BidCard {
                     player.data.cards.push(marketcard.data.0.object.clone());
                     player.data.update_interest(counter);
                     player.store();
-                    let mut owner = marketcard.data.0.deal()?;
+                    let mut owner = marketcard.data.deal()?;
                     if let Some(card_index) = owner.data.cards.iter().position(|c| c.marketid == marketcard.data.0.marketid) {
                         owner.data.remove_card(card_index);
                     }
                     owner.data.update_interest(counter);
                     owner.store();
-                    marketcard.store();
+                    MarketCard::save(&marketcard);
                     MarketCard::emit_event(state.event_id, &marketcard.data);
                     state.event_id += 1;
+                    state.active_listings = state.active_listings.saturating_sub(1);
                     Ok(())
-                } else if marketcard.data.0.object.marketid != 0 {
+                } else {
                     let prev_bidder = marketcard.data.0.get_bidder();
                     if prev_bidder.map_or(false, |x| x.bidder == player.player_id) {
                         let bidprice = prev_bidder.expect("").bidprice;
+                        if self.price <= bidprice {
+                            return Err(ERROR_BID_PRICE_INSUFFICIENT);
+                        }
                         player.data.cost_balance(self.price - bidprice)?;
                         player.data.update_interest(counter);
                         marketcard.data.0.set_bidder(Some (BidInfo {
@@ -300,7 +380,7 @@ impl CommandHandler for BidCard {
                             bidder: player.player_id.clone(),
                         }));
                     } else {
-                        let prev_bidder = marketcard.data.0.replace_bidder(player, self.price)?;
+                        let prev_bidder = marketcard.data.replace_bidder(player, self.price)?;
                         prev_bidder.map(|mut x| {
                             x.data.update_interest(counter);
                             x.store();
@@ -309,12 +389,10 @@ impl CommandHandler for BidCard {
 
                     player.store();
                     marketcard.data.0.settleinfo = 1;
-                    marketcard.store();
+                    MarketCard::save(&marketcard);
                     MarketCard::emit_event(state.event_id, &marketcard.data);
                     state.event_id += 1;
                     Ok(())
-                } else {
-                    Err(ERROR_CARD_IS_IN_USE)
                 }
             }
         }
@@ -331,9 +409,9 @@ pub struct Bounty {
 
 impl CommandHandler for Bounty {
     fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
-        let mut player = AutomataPlayer::get_from_pid(pid);
+        let mut player = AutomataPlayer::load(pid)?;
         match player.as_mut() {
-            None => Err(ERROR_PLAYER_ALREADY_EXIST),
+            None => Err(ERROR_PLAYER_NOT_EXIST),
             Some(player) => {
                 player.check_and_inc_nonce(nonce);
                 if self.bounty_index < 7 {
@@ -345,6 +423,7 @@ impl CommandHandler for Bounty {
                             player.data.redeem_info[self.bounty_index] += 1;
                             let reward = CONFIG.get_bounty_reward(redeem_info as u64);
                             player.data.inc_balance(reward);
+                            ledger::mint(reward);
                             player.data.inc_exp(5);
                             player.store();
                             Ok(())
@@ -354,10 +433,12 @@ impl CommandHandler for Bounty {
                     } else {
                         Err(ERROR_INDEX_OUT_OF_BOUND)
                     }
+                } else if self.bounty_index > 7 {
+                    Err(ERROR_INDEX_OUT_OF_BOUND)
                 } else {
-                    enforce(self.bounty_index == 7, "check bounty index");
-                    let counter = STATE.0.borrow().queue.counter;
-                    player.data.collect_interest(counter)?;
+                    let mut state = STATE.0.borrow_mut();
+                    let paid = player.data.collect_interest(state.queue.counter, state.interest_reserve)?;
+                    state.pay_interest(paid);
                     player.store();
                     Ok(())
                 }
@@ -370,57 +451,161 @@ impl CommandHandler for Bounty {
 #[derive (Clone)]
 pub struct Deposit {
     data: [u64; 3],
+    token: u64,
+    txref: u64, // L1 transaction reference, echoed in the deposit receipt event
+    sender: [u64; 3], // L1 address pending deposits are refunded to
 }
 
 impl CommandHandler for Deposit {
     fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
         //zkwasm_rust_sdk::dbg!("deposit\n");
-        let mut admin = AutomataPlayer::get_from_pid(pid).unwrap();
+        let mut admin = AutomataPlayer::load(pid)?.ok_or(ERROR_PLAYER_NOT_EXIST)?;
         admin.check_and_inc_nonce(nonce);
-        let mut player = AutomataPlayer::get_from_pid(&[self.data[0], self.data[1]]);
+        let target = [self.data[0], self.data[1]];
+        let amount = self.data[2];
+        let mut player = AutomataPlayer::load(&target)?;
         let mut state = STATE.0.borrow_mut();
         let counter = state.queue.counter;
-        match player.as_mut() {
-            None => {
-                let mut player = AutomataPlayer::new_from_pid([self.data[0], self.data[1]]);
-                player.data.inc_balance(self.data[2]);
-                player.data.update_interest(counter);
-                player.store();
-            }
+        let pool = state.bounty_pools[self.token as usize].checked_add(amount).ok_or(ERROR_BALANCE_OVERFLOW)?;
+        let status = match player.as_mut() {
             Some(player) => {
-                player.data.inc_balance(self.data[2]);
+                player.data.checked_inc_token_balance(self.token, amount)?;
                 player.data.update_interest(counter);
                 player.store();
+                DEPOSIT_CREDITED
             }
+            None => match CONFIG.deposit_policy() {
+                DEPOSIT_CREATE => {
+                    let mut player = AutomataPlayer::new_from_pid(target);
+                    player.data.checked_inc_token_balance(self.token, amount)?;
+                    player.data.update_interest(counter);
+                    player.data.last_regen_stamp = counter as u32;
+                    player.store();
+                    state.new_players += 1;
+                    DEPOSIT_CREATED
+                }
+                DEPOSIT_PENDING => {
+                    let mut pending = deposit::load(&target)?;
+                    pending.push(PendingDeposit {
+                        token: self.token,
+                        amount,
+                        counter,
+                        txref: self.txref,
+                        address: self.sender,
+                    });
+                    deposit::save(&target, &pending);
+                    DEPOSIT_PENDED
+                }
+                _ => return Err(ERROR_PLAYER_NOT_EXIST),
+            },
         };
-        state.bounty_pool += self.data[2];
+        state.bounty_pools[self.token as usize] = pool;
+        ledger::deposit(self.token, amount);
+        emit_deposit(self.txref, &target, self.token, amount, status);
         admin.store();
         Ok(()) // no error occurred
     }
 }
 
+#[derive (Clone)]
+pub struct RefundDeposit {
+    owner: [u64; 2],
+    address: [u64; 3], // receives the deposits that carry no sender, zero leaves them pending
+}
+
+// Sends the expired pending deposits of owner back to their L1 sender.
+// WithdrawInfo carries 32 bit amounts, so larger deposits are refunded in several settlements.
+impl CommandHandler for RefundDeposit {
+    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
+        let mut admin = AutomataPlayer::load(pid)?.ok_or(ERROR_PLAYER_NOT_EXIST)?;
+        admin.check_and_inc_nonce(nonce);
+        let mut state = STATE.0.borrow_mut();
+        let counter = state.queue.counter;
+        let (refunds, kept): (Vec<_>, Vec<_>) = deposit::load(&self.owner)?
+            .into_iter()
+            .partition(|d| d.refund_address(counter, &self.address).is_some());
+        if refunds.is_empty() {
+            return Err(ERROR_NO_REFUNDABLE_DEPOSIT);
+        }
+        // every token is resolved and every pool checked before anything is settled
+        let mut needed = vec![0u64; state.bounty_pools.len()];
+        let mut token_ids = vec![];
+        for d in refunds.iter() {
+            token_ids.push(CONFIG.get_token(d.token).ok_or(ERROR_INVALID_STORAGE_DATA)?.token_id);
+            let total = needed.get_mut(d.token as usize).ok_or(ERROR_INVALID_STORAGE_DATA)?;
+            *total = total.saturating_add(d.amount);
+        }
+        if needed.iter().zip(state.bounty_pools.iter()).any(|(n, pool)| n > pool) {
+            return Err(ERROR_NOT_ENOUGH_POOL);
+        }
+        for (d, token_id) in refunds.iter().zip(token_ids) {
+            let address = d.refund_address(counter, &self.address).unwrap();
+            let mut left = d.amount;
+            while left > 0 {
+                let amount = left.min(0xffffffff);
+                let data = [address[0] | amount, address[1], address[2]];
+                SettlementInfo::append_settlement(WithdrawInfo::new(&data, token_id));
+                left -= amount;
+            }
+            state.bounty_pools[d.token as usize] -= d.amount;
+            ledger::withdraw(d.token, d.amount);
+        }
+        deposit::save(&self.owner, &kept);
+        admin.store();
+        Ok(())
+    }
+}
+
 #[derive (Clone)]
 pub struct Withdraw {
     data: [u64; 3],
+    token: u64,
 }
 
+// Withdrawals leave the balance and the pool at once and are settled right away,
+// or queued until the counter passes the configured delay
 impl CommandHandler for Withdraw {
     fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
-        let mut player = AutomataPlayer::get_from_pid(pid);
+        let mut player = AutomataPlayer::load(pid)?;
         match player.as_mut() {
             None => Err(ERROR_PLAYER_NOT_EXIST),
             Some(player) => {
                 player.check_and_inc_nonce(nonce);
+                if player.data.pending_withdraw.is_some() {
+                    return Err(ERROR_WITHDRAW_PENDING);
+                }
                 let mut state = STATE.0.borrow_mut();
                 let amount = self.data[0] & 0xffffffff;
-                if amount <= state.bounty_pool {
+                let pool = self.token as usize;
+                if amount <= state.bounty_pools[pool] {
                     let counter = state.queue.counter;
-                    player.data.cost_balance(amount)?;
-                    let withdrawinfo =
-                        WithdrawInfo::new(&[self.data[0], self.data[1], self.data[2]], 0);
-                    SettlementInfo::append_settlement(withdrawinfo);
+                    let limits = state.withdraw_limits.clone();
+                    // windows are tracked by their start counter, which stays comparable when
+                    // SetWithdrawLimits changes the window size
+                    let window = counter - counter % limits.window;
+                    let withdrawn = if state.withdraw_window == window { state.withdrawn } else { 0 };
+                    if withdrawn + amount > limits.global_limit {
+                        return Err(ERROR_WITHDRAW_LIMIT_EXCEEDED);
+                    }
+                    player.data.track_withdraw(window, amount, limits.player_limit)?;
+                    player.data.cost_token_balance(self.token, amount)?;
+                    if limits.delay == 0 {
+                        let token_id = CONFIG.get_token(self.token).ok_or(ERROR_INVALID_PARAMS)?.token_id;
+                        let withdrawinfo =
+                            WithdrawInfo::new(&[self.data[0], self.data[1], self.data[2]], token_id);
+                        SettlementInfo::append_settlement(withdrawinfo);
+                    } else {
+                        player.data.pending_withdraw = Some(PendingWithdraw {
+                            token: self.token,
+                            data: self.data,
+                            mature: counter.saturating_add(limits.delay),
+                        });
+                    }
                     player.data.update_interest(counter);
-                    state.bounty_pool -= amount;
+                    state.withdraw_window = window;
+                    state.withdrawn = withdrawn + amount;
+                    state.bounty_pools[pool] -= amount;
+                    ledger::withdraw(self.token, amount);
                     player.store();
                     Ok(())
                 } else {
@@ -431,6 +616,171 @@ impl CommandHandler for Withdraw {
     }
 }
 
+#[derive (Clone)]
+pub struct ClaimWithdraw {
+}
+
+impl CommandHandler for ClaimWithdraw {
+    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
+        let mut player = AutomataPlayer::load(pid)?;
+        match player.as_mut() {
+            None => Err(ERROR_PLAYER_NOT_EXIST),
+            Some(player) => {
+                player.check_and_inc_nonce(nonce);
+                let pending = player.data.pending_withdraw.clone().ok_or(ERROR_NO_PENDING_WITHDRAW)?;
+                if STATE.0.borrow().queue.counter < pending.mature {
+                    return Err(ERROR_WITHDRAW_NOT_MATURED);
+                }
+                let token_id = CONFIG.get_token(pending.token).ok_or(ERROR_INVALID_PARAMS)?.token_id;
+                SettlementInfo::append_settlement(WithdrawInfo::new(&pending.data, token_id));
+                player.data.pending_withdraw = None;
+                player.store();
+                Ok(())
+            }
+        }
+    }
+}
+
+// Cancelled amounts still count against the limits of their window, so cancelling can not be
+// used to withdraw more than the limit
+#[derive (Clone)]
+pub struct CancelWithdraw {
+}
+
+impl CommandHandler for CancelWithdraw {
+    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
+        let mut player = AutomataPlayer::load(pid)?;
+        match player.as_mut() {
+            None => Err(ERROR_PLAYER_NOT_EXIST),
+            Some(player) => {
+                player.check_and_inc_nonce(nonce);
+                let pending = player.data.pending_withdraw.take().ok_or(ERROR_NO_PENDING_WITHDRAW)?;
+                let amount = pending.data[0] & 0xffffffff;
+                let mut state = STATE.0.borrow_mut();
+                player.data.inc_token_balance(pending.token, amount);
+                player.data.update_interest(state.queue.counter);
+                state.bounty_pools[pending.token as usize] += amount;
+                ledger::cancel_withdraw(pending.token, amount);
+                player.store();
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive (Clone, Serialize)]
+pub struct WithdrawLimits {
+    window: u64, // ticks per limit window
+    player_limit: u64, // amount a player can withdraw per window
+    global_limit: u64, // amount all players together can withdraw per window
+    delay: u64, // ticks before a queued withdrawal can be claimed, 0 settles at once
+}
+
+const MAX_WITHDRAW_WINDOW: u64 = 17280 * 30; // 30 days
+const MAX_WITHDRAW_DELAY: u64 = 17280 * 30;
+
+impl Default for WithdrawLimits {
+    fn default() -> Self {
+        WithdrawLimits {
+            window: 17280,
+            player_limit: 1000000,
+            global_limit: 20000000,
+            delay: 0,
+        }
+    }
+}
+
+#[derive (Clone)]
+pub struct SetWithdrawLimits {
+    limits: WithdrawLimits,
+}
+
+impl CommandHandler for SetWithdrawLimits {
+    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
+        let mut admin = AutomataPlayer::load(pid)?.ok_or(ERROR_PLAYER_NOT_EXIST)?;
+        admin.check_and_inc_nonce(nonce);
+        if self.limits.window == 0 {
+            return Err(ERROR_INVALID_PARAMS);
+        }
+        if self.limits.window > MAX_WITHDRAW_WINDOW || self.limits.delay > MAX_WITHDRAW_DELAY {
+            return Err(ERROR_CONFIG_OUT_OF_BOUNDS);
+        }
+        let mut state = STATE.0.borrow_mut();
+        state.withdraw_limits = self.limits.clone();
+        admin.store();
+        Ok(())
+    }
+}
+
+#[derive (Clone)]
+pub struct SetPause {
+    flags: u64,
+}
+
+impl CommandHandler for SetPause {
+    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
+        let mut admin = AutomataPlayer::load(pid)?.ok_or(ERROR_PLAYER_NOT_EXIST)?;
+        admin.check_and_inc_nonce(nonce);
+        STATE.0.borrow_mut().paused = self.flags;
+        admin.store();
+        Ok(())
+    }
+}
+
+#[derive (Clone)]
+pub struct AddAdmin {
+    admin: [u64; 2],
+    roles: u64,
+}
+
+impl CommandHandler for AddAdmin {
+    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
+        let mut admin = AutomataPlayer::load(pid)?.ok_or(ERROR_PLAYER_NOT_EXIST)?;
+        admin.check_and_inc_nonce(nonce);
+        if self.admin == *pid && self.roles & ROLE_SUPERADMIN == 0 {
+            return Err(ERROR_INVALID_PARAMS); // the signing superadmin can not drop its own role
+        }
+        admin::set_roles(&self.admin, self.roles);
+        admin.store();
+        Ok(())
+    }
+}
+
+#[derive (Clone)]
+pub struct RemoveAdmin {
+    admin: [u64; 2],
+}
+
+impl CommandHandler for RemoveAdmin {
+    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
+        let mut admin = AutomataPlayer::load(pid)?.ok_or(ERROR_PLAYER_NOT_EXIST)?;
+        admin.check_and_inc_nonce(nonce);
+        if self.admin == *pid {
+            return Err(ERROR_INVALID_PARAMS); // keeps at least the signing superadmin
+        }
+        admin::set_roles(&self.admin, 0);
+        admin.store();
+        Ok(())
+    }
+}
+
+#[derive (Clone)]
+pub struct SetConfig {
+    param: u64,
+    value: u64,
+}
+
+impl CommandHandler for SetConfig {
+    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
+        let mut admin = AutomataPlayer::load(pid)?.ok_or(ERROR_PLAYER_NOT_EXIST)?;
+        admin.check_and_inc_nonce(nonce);
+        let old = config::update_param(self.param, self.value)?;
+        emit_config_change(self.param, old, self.value, STATE.0.borrow().queue.counter);
+        admin.store();
+        Ok(())
+    }
+}
+
 
 #[derive (Clone)]
 pub struct CollectEnergy {
@@ -438,12 +788,13 @@ pub struct CollectEnergy {
 
 impl CommandHandler for CollectEnergy {
     fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
-        let mut player = AutomataPlayer::get_from_pid(pid);
+        let mut player = AutomataPlayer::load(pid)?;
         match player.as_mut() {
-            None => Err(ERROR_PLAYER_ALREADY_EXIST),
+            None => Err(ERROR_PLAYER_NOT_EXIST),
             Some(player) => {
                 player.check_and_inc_nonce(nonce);
                 let counter = STATE.0.borrow().queue.counter;
+                player.data.regen_energy(counter);
                 player.data.collect_energy(counter)?;
                 player.store();
                 Ok(())
@@ -452,109 +803,263 @@ impl CommandHandler for CollectEnergy {
     }
 }
 
+#[derive (Clone)]
+pub struct GiftEnergy {
+    receiver: [u64; 2],
+    amount: u16,
+}
+
+impl CommandHandler for GiftEnergy {
+    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
+        let mut player = AutomataPlayer::load(pid)?;
+        match player.as_mut() {
+            None => Err(ERROR_PLAYER_NOT_EXIST),
+            Some(player) => {
+                player.check_and_inc_nonce(nonce);
+                if self.receiver == *pid || self.amount == 0 {
+                    return Err(ERROR_INVALID_PARAMS);
+                }
+                let mut receiver = AutomataPlayer::load(&self.receiver)?.ok_or(ERROR_PLAYER_NOT_EXIST)?;
+                let counter = STATE.0.borrow().queue.counter;
+                player.data.regen_energy(counter);
+                receiver.data.regen_energy(counter);
+                let received = player.data.send_energy(self.amount, counter)?;
+                receiver.data.receive_energy(received, counter)?;
+                player.store();
+                receiver.store();
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive (Clone)]
+pub struct BuyEnergyPack {
+    packs: u64,
+}
+
+impl CommandHandler for BuyEnergyPack {
+    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
+        let mut player = AutomataPlayer::load(pid)?;
+        match player.as_mut() {
+            None => Err(ERROR_PLAYER_NOT_EXIST),
+            Some(player) => {
+                player.check_and_inc_nonce(nonce);
+                let counter = STATE.0.borrow().queue.counter;
+                player.data.regen_energy(counter);
+                player.data.buy_energy_pack(self.packs)?;
+                player.data.update_interest(counter);
+                player.store();
+                Ok(())
+            }
+        }
+    }
+}
 
 
-const INSTALL_PLAYER: u64 = 1;
-const INSTALL_OBJECT: u64 = 2;
-const RESTART_OBJECT: u64 = 3;
-const UPGRADE_OBJECT: u64 = 4;
-const INSTALL_CARD: u64 = 5;
-const WITHDRAW: u64 = 6;
-const DEPOSIT: u64 = 7;
-const BOUNTY: u64 = 8;
-const COLLECT_ENERGY: u64 = 9;
-const LIST_CARD_IN_MARKET: u64 = 10;
-const BID_CARD: u64 = 11; // index, price
-const SELL_CARD: u64 = 12;
 
 impl Transaction {
     pub fn decode_error(e: u32) -> &'static str {
-        match e {
-            ERROR_PLAYER_NOT_EXIST => "PlayerNotExist",
-            ERROR_PLAYER_ALREADY_EXIST => "PlayerAlreadyExist",
-            ERROR_NOT_ENOUGH_BALANCE => "NotEnoughBalance",
-            ERROR_INDEX_OUT_OF_BOUND => "IndexOutofBound",
-            ERROR_NOT_ENOUGH_RESOURCE => "NotEnoughResource",
-            ERROR_NOT_ENOUGH_LEVEL => "NotEnoughLevel",
-            ERROR_NOT_ENOUGH_POOL => "NotEnoughFundInPool",
-            ERROR_CARD_IS_IN_USE => "CardIsInUse",
-            ERROR_BID_PRICE_INSUFFICIENT => "BidPriceInSufficient",
-            ERROR_NO_BIDDER=> "NoBidder",
-            _ => "Unknown",
-        }
+        Error::decode(e)
     }
     pub fn decode(params: &[u64]) -> Self {
-        let cmd = params[0] & 0xff;
-        let nonce = params[0] >> 16;
-        let command = if cmd == WITHDRAW {
-            enforce(params[1] == 0, "check withdraw index"); // only token index 0 is supported
-            Command::Withdraw (Withdraw {
-                data: [params[2], params[3], params[4]]
-            })
-        } else if cmd == INSTALL_OBJECT {
-            Command::InstallObject (InstallObject {
+        Self::try_decode(params).unwrap_or_else(|e| Transaction {
+            command: Command::Invalid(e),
+            nonce: params.first().map_or(0, |x| x >> 16),
+        })
+    }
+
+    pub fn try_decode(params: &[u64]) -> Result<Self, u32> {
+        let header = *params.first().ok_or(ERROR_INVALID_PARAMS)?;
+        let cmd = header & 0xff;
+        let nonce = header >> 16;
+        let opcode = Opcode::lookup(cmd).ok_or(ERROR_UNKNOWN_COMMAND)?;
+        if params.len() < opcode.params + 1 {
+            return Err(ERROR_INVALID_PARAMS);
+        }
+        let command = match cmd {
+            WITHDRAW => {
+                if CONFIG.get_token(params[1]).is_none() {
+                    return Err(ERROR_INVALID_PARAMS);
+                }
+                Command::Withdraw (Withdraw {
+                    data: [params[2], params[3], params[4]],
+                    token: params[1],
+                })
+            },
+            INSTALL_OBJECT => Command::InstallObject (InstallObject {
                 object_index: params[1] as usize,
                 modifiers: params[2].to_le_bytes(),
-            })
-        } else if cmd == RESTART_OBJECT {
-            Command::RestartObject (RestartObject {
+            }),
+            RESTART_OBJECT => Command::RestartObject (RestartObject {
                 object_index: params[1] as usize,
                 modifiers: params[2].to_le_bytes(),
-            })
-        } else if cmd == DEPOSIT {
-            zkwasm_rust_sdk::dbg!("deposit params: {:?}\n", params);
-            enforce(params[3] == 0, "check deposit index"); // only token index 0 is supported
-            Command::Deposit (Deposit {
-                data: [params[1], params[2], params[4]]
-            })
-        } else if cmd == UPGRADE_OBJECT {
-            Command::UpgradeObject(UpgradeObject {
+            }),
+            DEPOSIT => {
+                zkwasm_rust_sdk::dbg!("deposit params: {:?}\n", params);
+                let optional = |i: usize| params.get(i).copied().unwrap_or(0);
+                if CONFIG.get_token(params[3]).is_none() || optional(6) & 0xffffffff != 0 {
+                    return Err(ERROR_INVALID_PARAMS);
+                }
+                Command::Deposit (Deposit {
+                    data: [params[1], params[2], params[4]],
+                    token: params[3],
+                    txref: optional(5),
+                    sender: [optional(6), optional(7), optional(8)],
+                })
+            },
+            UPGRADE_OBJECT => Command::UpgradeObject(UpgradeObject {
                 object_index: params[1] as usize,
                 feature_index: params[2] as usize,
-            })
-        } else if cmd == BOUNTY {
-            Command::Bounty (Bounty {
+            }),
+            BOUNTY => Command::Bounty (Bounty {
                 bounty_index: params[1] as usize
-            })
-        } else if cmd == INSTALL_CARD {
-            Command::InstallCard (InstallCard {})
-        } else if cmd == BID_CARD {
-            Command::BidCard (BidCard {
+            }),
+            INSTALL_CARD => Command::InstallCard (InstallCard {}),
+            BID_CARD => Command::BidCard (BidCard {
                 marketindex: params[1],
                 price: params[2],
-            })
-        } else if cmd == SELL_CARD {
-            Command::SellCard (SellCard {
+            }),
+            SELL_CARD => Command::SellCard (SellCard {
                 card_index: params[1] as usize,
-            })
-        } else if cmd == LIST_CARD_IN_MARKET {
-            Command::ListCardInMarket (ListCardInMarket{
+            }),
+            LIST_CARD_IN_MARKET => Command::ListCardInMarket (ListCardInMarket{
                 card_index: params[1] as usize,
                 ask_price: params[2],
-            })
-        } else if cmd == INSTALL_PLAYER {
-            Command::InstallPlayer
-        } else if cmd == COLLECT_ENERGY {
-            Command::CollectEnergy(CollectEnergy {})
-        } else {
-            Command::Tick
+            }),
+            INSTALL_PLAYER => Command::InstallPlayer,
+            COLLECT_ENERGY => Command::CollectEnergy(CollectEnergy {}),
+            GIFT_ENERGY => {
+                if params[3] > 0xffff {
+                    return Err(ERROR_INVALID_PARAMS);
+                }
+                Command::GiftEnergy(GiftEnergy {
+                    receiver: [params[1], params[2]],
+                    amount: params[3] as u16,
+                })
+            },
+            BUY_ENERGY_PACK => Command::BuyEnergyPack(BuyEnergyPack {
+                packs: params[1],
+            }),
+            CLAIM_WITHDRAW => Command::ClaimWithdraw(ClaimWithdraw {}),
+            ADD_ADMIN => {
+                if params[3] & !ROLE_ALL != 0 {
+                    return Err(ERROR_INVALID_PARAMS);
+                }
+                Command::AddAdmin(AddAdmin {
+                    admin: [params[1], params[2]],
+                    roles: params[3],
+                })
+            },
+            REFUND_DEPOSIT => {
+                let optional = |i: usize| params.get(i).copied().unwrap_or(0);
+                if optional(3) & 0xffffffff != 0 {
+                    return Err(ERROR_INVALID_PARAMS);
+                }
+                Command::RefundDeposit(RefundDeposit {
+                    owner: [params[1], params[2]],
+                    address: [optional(3), optional(4), optional(5)],
+                })
+            },
+            SET_CONFIG => {
+                if params[1] as usize >= PARAMS.len() {
+                    return Err(ERROR_INVALID_PARAMS);
+                }
+                Command::SetConfig(SetConfig {
+                    param: params[1],
+                    value: params[2],
+                })
+            },
+            REMOVE_ADMIN => Command::RemoveAdmin(RemoveAdmin {
+                admin: [params[1], params[2]],
+            }),
+            SET_PAUSE => {
+                if params[1] & !PAUSE_NAMES.iter().fold(0, |m, (f, _)| m | f) != 0 {
+                    return Err(ERROR_INVALID_PARAMS);
+                }
+                Command::SetPause(SetPause {
+                    flags: params[1],
+                })
+            },
+            CANCEL_WITHDRAW => Command::CancelWithdraw(CancelWithdraw {}),
+            SET_WITHDRAW_LIMITS => Command::SetWithdrawLimits(SetWithdrawLimits {
+                limits: WithdrawLimits {
+                    window: params[1],
+                    player_limit: params[2],
+                    global_limit: params[3],
+                    delay: params[4],
+                },
+            }),
+            TICK => Command::Tick,
+            _ => unreachable!(),
         };
 
-        Transaction {
+        Ok(Transaction {
             command,
             nonce,
-        }
+        })
+    }
+
+    /// Inverse of decode, returns None for transactions that failed to decode
+    pub fn encode(&self) -> Option<Vec<u64>> {
+        let nonce = self.nonce;
+        let params = match &self.command {
+            Command::UpgradeObject(cmd) => encode::upgrade_object(nonce, cmd.object_index as u64, cmd.feature_index as u64),
+            Command::InstallObject(cmd) => encode::install_object(nonce, cmd.object_index as u64, cmd.modifiers),
+            Command::RestartObject(cmd) => encode::restart_object(nonce, cmd.object_index as u64, cmd.modifiers),
+            Command::InstallCard(_) => encode::install_card(nonce),
+            Command::ListCardInMarket(cmd) => encode::list_card_in_market(nonce, cmd.card_index as u64, cmd.ask_price),
+            Command::SellCard(cmd) => encode::sell_card(nonce, cmd.card_index as u64),
+            Command::BidCard(cmd) => encode::bid_card(nonce, cmd.marketindex, cmd.price),
+            Command::Withdraw(cmd) => encode::withdraw_token(nonce, cmd.token, cmd.data),
+            Command::Deposit(cmd) => encode::deposit_token(nonce, [cmd.data[0], cmd.data[1]], cmd.token, cmd.data[2], cmd.txref, cmd.sender),
+            Command::Bounty(cmd) => encode::bounty(nonce, cmd.bounty_index as u64),
+            Command::InstallPlayer => encode::install_player(nonce),
+            Command::CollectEnergy(_) => encode::collect_energy(nonce),
+            Command::GiftEnergy(cmd) => encode::gift_energy(nonce, cmd.receiver, cmd.amount as u64),
+            Command::BuyEnergyPack(cmd) => encode::buy_energy_pack(nonce, cmd.packs),
+            Command::ClaimWithdraw(_) => encode::claim_withdraw(nonce),
+            Command::SetPause(cmd) => encode::set_pause(nonce, cmd.flags),
+            Command::AddAdmin(cmd) => encode::add_admin(nonce, cmd.admin, cmd.roles),
+            Command::RemoveAdmin(cmd) => encode::remove_admin(nonce, cmd.admin),
+            Command::SetConfig(cmd) => encode::set_config(nonce, cmd.param, cmd.value),
+            Command::RefundDeposit(cmd) if cmd.address == [0; 3] => encode::refund_deposit(nonce, cmd.owner),
+            Command::RefundDeposit(cmd) => encode::refund_deposit_to(nonce, cmd.owner, cmd.address),
+            Command::CancelWithdraw(_) => encode::cancel_withdraw(nonce),
+            Command::SetWithdrawLimits(cmd) => encode::set_withdraw_limits(
+                nonce,
+                cmd.limits.window,
+                cmd.limits.player_limit,
+                cmd.limits.global_limit,
+                cmd.limits.delay,
+            ),
+            Command::Tick => encode::tick(nonce),
+            Command::Invalid(_) => return None,
+        };
+        Some(params)
     }
 
     pub fn install_player(pid: &[u64; 2]) -> Result<(), u32> {
-        let player = AutomataPlayer::get_from_pid(pid);
+        let player = AutomataPlayer::load(pid)?;
         let counter = STATE.0.borrow().queue.counter;
         match player {
             Some(_) => Err(ERROR_PLAYER_ALREADY_EXIST),
             None => {
                 let mut player = AutomataPlayer::new_from_pid(*pid);
+                let pending = deposit::load(pid)?;
+                for d in pending.iter() {
+                    player.data.checked_inc_token_balance(d.token, d.amount)?;
+                }
                 player.data.update_interest(counter);
+                player.data.last_regen_stamp = counter as u32;
                 player.store();
+                STATE.0.borrow_mut().new_players += 1;
+                if !pending.is_empty() {
+                    deposit::save(pid, &[]);
+                    for d in pending.iter() {
+                        emit_deposit(d.txref, pid, d.token, d.amount, DEPOSIT_CLAIMED);
+                    }
+                }
                 Ok(())
             }
         }
@@ -562,10 +1067,11 @@ impl Transaction {
     }
 
     pub fn collect_energy(pid: &[u64; 2]) -> Result<(), u32> {
-        let player = AutomataPlayer::get_from_pid(pid);
+        let player = AutomataPlayer::load(pid)?;
         let counter = STATE.0.borrow().queue.counter;
         match player {
             Some(mut player) => {
+                player.data.regen_energy(counter);
                 player.data.collect_energy(counter)?;
                 player.store();
                 Ok(())
@@ -575,11 +1081,12 @@ impl Transaction {
     }
 
     pub fn collect_interest(pid: &[u64; 2]) -> Result<(), u32> {
-        let player = AutomataPlayer::get_from_pid(pid);
-        let counter = STATE.0.borrow().queue.counter;
+        let player = AutomataPlayer::load(pid)?;
+        let mut state = STATE.0.borrow_mut();
         match player {
             Some(mut player) => {
-                player.data.collect_interest(counter)?;
+                let paid = player.data.collect_interest(state.queue.counter, state.interest_reserve)?;
+                state.pay_interest(paid);
                 player.store();
                 Ok(())
             }
@@ -587,48 +1094,75 @@ impl Transaction {
         }
     }
 
-    pub fn process(&self, pkey: &[u64; 4], rand: &[u64; 4]) -> Vec<u64> {
-        let e = match self.command.clone() {
-            Command::InstallPlayer => Self::install_player(&AutomataPlayer::pkey_to_pid(&pkey))
-                .map_or_else(|e| e, |_| 0),
-            Command::InstallObject(cmd) => cmd.handle(&AutomataPlayer::pkey_to_pid(&pkey), self.nonce, rand)
-                .map_or_else(|e| e, |_| 0),
-            Command::CollectEnergy(cmd) => cmd.handle(&AutomataPlayer::pkey_to_pid(&pkey), self.nonce, rand)
-                .map_or_else(|e| e, |_| 0),
-            Command::RestartObject(cmd) => cmd.handle(&AutomataPlayer::pkey_to_pid(&pkey), self.nonce, rand)
-                .map_or_else(|e| e, |_| 0),
-            Command::UpgradeObject(cmd) => cmd.handle(&AutomataPlayer::pkey_to_pid(&pkey), self.nonce, rand)
-                .map_or_else(|e| e, |_| 0),
-            Command::Withdraw(cmd)=> cmd.handle(&AutomataPlayer::pkey_to_pid(pkey), self.nonce, rand)
-                .map_or_else(|e| e, |_| 0),
-            Command::InstallCard(cmd) => cmd.handle(&AutomataPlayer::pkey_to_pid(pkey), self.nonce, rand)
-                .map_or_else(|e| e, |_| 0),
-            Command::ListCardInMarket(cmd) => cmd.handle(&AutomataPlayer::pkey_to_pid(pkey), self.nonce, rand)
-                .map_or_else(|e| e, |_| 0),
-            Command::SellCard(cmd) => cmd.handle(&AutomataPlayer::pkey_to_pid(pkey), self.nonce, rand)
-                .map_or_else(|e| e, |_| 0),
-            Command::BidCard(cmd) => cmd.handle(&AutomataPlayer::pkey_to_pid(pkey), self.nonce, rand)
-                .map_or_else(|e| e, |_| 0),
-
+    fn dispatch(&self, pkey: &[u64; 4], rand: &[u64; 4]) -> Result<(), u32> {
+        let pid = AutomataPlayer::pkey_to_pid(pkey);
+        match self.command.clone() {
+            Command::InstallPlayer => Self::install_player(&pid),
+            Command::InstallObject(cmd) => cmd.handle(&pid, self.nonce, rand),
+            Command::CollectEnergy(cmd) => cmd.handle(&pid, self.nonce, rand),
+            Command::RestartObject(cmd) => cmd.handle(&pid, self.nonce, rand),
+            Command::UpgradeObject(cmd) => cmd.handle(&pid, self.nonce, rand),
+            Command::Withdraw(cmd)=> cmd.handle(&pid, self.nonce, rand),
+            Command::InstallCard(cmd) => cmd.handle(&pid, self.nonce, rand),
+            Command::ListCardInMarket(cmd) => cmd.handle(&pid, self.nonce, rand),
+            Command::SellCard(cmd) => cmd.handle(&pid, self.nonce, rand),
+            Command::BidCard(cmd) => cmd.handle(&pid, self.nonce, rand),
             Command::Deposit(cmd) => {
-                enforce(*pkey == *ADMIN_PUBKEY, "check admin key of deposit");
-                cmd.handle(&AutomataPlayer::pkey_to_pid(pkey), self.nonce, rand)
-                    .map_or_else(|e| e, |_| 0)
+                admin::check_role(&pid, ROLE_DEPOSITOR)?;
+                cmd.handle(&pid, self.nonce, rand)
+            },
+            Command::Bounty(cmd) => cmd.handle(&pid, self.nonce, rand),
+            Command::GiftEnergy(cmd) => cmd.handle(&pid, self.nonce, rand),
+            Command::BuyEnergyPack(cmd) => cmd.handle(&pid, self.nonce, rand),
+            Command::ClaimWithdraw(cmd) => cmd.handle(&pid, self.nonce, rand),
+            Command::CancelWithdraw(cmd) => cmd.handle(&pid, self.nonce, rand),
+            Command::SetWithdrawLimits(cmd) => {
+                admin::check_role(&pid, ROLE_CONFIG_MANAGER)?;
+                cmd.handle(&pid, self.nonce, rand)
+            },
+            Command::SetPause(cmd) => {
+                admin::check_role(&pid, ROLE_CONFIG_MANAGER)?;
+                cmd.handle(&pid, self.nonce, rand)
+            },
+            Command::AddAdmin(cmd) => {
+                admin::check_role(&pid, ROLE_SUPERADMIN)?;
+                cmd.handle(&pid, self.nonce, rand)
+            },
+            Command::RemoveAdmin(cmd) => {
+                admin::check_role(&pid, ROLE_SUPERADMIN)?;
+                cmd.handle(&pid, self.nonce, rand)
+            },
+            Command::SetConfig(cmd) => {
+                admin::check_role(&pid, ROLE_CONFIG_MANAGER)?;
+                cmd.handle(&pid, self.nonce, rand)
+            },
+            Command::RefundDeposit(cmd) => {
+                admin::check_role(&pid, ROLE_DEPOSITOR)?;
+                cmd.handle(&pid, self.nonce, rand)
             },
-            Command::Bounty(cmd) => cmd.handle(&AutomataPlayer::pkey_to_pid(pkey), self.nonce, rand)
-                .map_or_else(|e| e, |_| 0),
-
             Command::Tick => {
-                enforce(*pkey == *ADMIN_PUBKEY, "check admin key");
+                admin::check_role(&pid, ROLE_TICKER)?;
                 zkwasm_rust_sdk::dbg!("perform borrow ....\n");
                 let mut state = STATE.0.borrow_mut();
                 zkwasm_rust_sdk::dbg!("perform borrow done.n");
                 state.queue.tick();
+                state.halted_objects += events::take_halts();
                 zkwasm_rust_sdk::dbg!("tick done. n");
                 //STATE.0.borrow_mut().queue.tick();
-                0
+                Ok(())
             }
+            Command::Invalid(e) => Err(e),
+        }
+    }
+
+    pub fn process(&self, pkey: &[u64; 4], rand: &[u64; 4]) -> Vec<u64> {
+        let paused = STATE.0.borrow().paused & self.command.pause_flags() != 0;
+        let result = if paused {
+            Err(ERROR_PAUSED)
+        } else {
+            self.dispatch(pkey, rand)
         };
+        let e = result.map_or_else(|e| e, |_| 0);
         let event_id = STATE.0.borrow().event_id;
         let events = clear_events(vec![e as u64, event_id]);
         zkwasm_rust_sdk::dbg!("events: {:?}", events);
@@ -645,17 +1179,45 @@ lazy_static::lazy_static! {
 
 pub struct State {
     supplier: u64,
-    bounty_pool: u64,
+    bounty_pools: Vec<u64>, // indexed by token, bounty_pools[0] is the treasure pool
+    interest_reserve: u64,
+    withdraw_limits: WithdrawLimits,
+    withdraw_window: u64, // start counter of the window of withdrawn
+    withdrawn: u64, // amount withdrawn by all players in withdraw_window
+    paused: u64, // PAUSE_* flags set by SetPause
+    new_players: u64, // players created since counters_since
+    halted_objects: u64, // halted by the queue since counters_since and not restarted yet
+    active_listings: u64, // market listings that are not settled yet
+    counters_since: u64, // counter from which players and halts are counted, the upgrade to v7 or 0
     start_time_stamp: u64,
     market_id: u64,
     event_id: u64,
     queue: EventQueue<Event>,
 }
 
+#[derive(Debug, Serialize)]
+struct EconomyObserve {
+    minted: u64,
+    deposited: u64,
+    withdrawn: u64,
+}
+
 #[derive(Debug, Serialize)]
 struct StateObserve {
     bounty_pool: u64,
+    token_pools: Vec<u64>,
+    paused: Vec<&'static str>,
+    interest_reserve: u64,
     counter: u64,
+    supplier: u64,
+    market_id: u64,
+    event_id: u64,
+    new_players: u64,
+    halted_objects: u64,
+    queue_depth: u64, // every running object has exactly one event in the queue, halted ones have none
+    active_listings: u64,
+    counters_since: u64, // new_players and halted_objects only count what happened after it
+    economy: EconomyObserve, // treasure minted, deposited and withdrawn since genesis
 }
 
 impl State {
@@ -663,24 +1225,122 @@ impl State {
         State {
             supplier: 1000,
             start_time_stamp: 0,
-            bounty_pool: 20000000,
+            bounty_pools: State::initial_pools(20000000),
+            interest_reserve: CONFIG.get_interest_reserve(),
+            withdraw_limits: WithdrawLimits::default(),
+            withdraw_window: 0,
+            withdrawn: 0,
+            paused: 0,
+            new_players: 0,
+            halted_objects: 0,
+            active_listings: 0,
+            counters_since: 0,
             market_id: 1,
             event_id: 1,
             queue: EventQueue::new(),
         }
     }
+    /// Drops the in memory state, used by the native harness between runs
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn reset() {
+        *STATE.0.borrow_mut() = State::new();
+        ledger::reset(&STATE.0.borrow().bounty_pools);
+        config::load_params();
+    }
+
+    pub fn counter() -> u64 {
+        STATE.0.borrow().queue.counter
+    }
+
+    // one pool per registered token, pools of tokens added to Config later start empty
+    fn initial_pools(treasure_pool: u64) -> Vec<u64> {
+        let mut pools = vec![0; CONFIG.token_count().max(1)];
+        pools[0] = treasure_pool;
+        pools
+    }
+
+    pub fn bounty_pool() -> u64 {
+        STATE.0.borrow().bounty_pools[0]
+    }
+
+    pub fn token_pool(token: u64) -> u64 {
+        STATE.0.borrow().bounty_pools.get(token as usize).copied().unwrap_or(0)
+    }
+
+    /// Names of the paused command classes
+    pub fn paused() -> Vec<&'static str> {
+        let flags = STATE.0.borrow().paused;
+        PAUSE_NAMES.iter().filter(|(f, _)| flags & f != 0).map(|(_, name)| *name).collect()
+    }
+
+    pub fn interest_reserve() -> u64 {
+        STATE.0.borrow().interest_reserve
+    }
+
+    // interest leaves the reserve and the claim fee flows back into it, so only the net
+    // interest is emitted. collect_interest rejects claims that do not exceed the fee.
+    fn pay_interest(&mut self, paid: u64) {
+        let fee = CONFIG.get_interest_fee();
+        self.interest_reserve = (self.interest_reserve - paid).saturating_add(fee);
+        ledger::mint(paid - fee);
+    }
+
+    pub fn market_id() -> u64 {
+        STATE.0.borrow().market_id
+    }
+
+    pub fn event_id() -> u64 {
+        STATE.0.borrow().event_id
+    }
+
+    pub fn queue_len() -> usize {
+        STATE.0.borrow().queue.list.len()
+    }
+
     pub fn snapshot() -> String {
-        let counter = STATE.0.borrow().queue.counter;
-        let bounty_pool = STATE.0.borrow().bounty_pool;
-        let state = StateObserve {
-            counter,
-            bounty_pool
+        let paused = State::paused();
+        let ledger = ledger::get();
+        let treasure = ledger.pools.first().cloned().unwrap_or_default();
+        let state = STATE.0.borrow();
+        let observe = StateObserve {
+            counter: state.queue.counter,
+            bounty_pool: state.bounty_pools[0],
+            token_pools: state.bounty_pools[1..].to_vec(),
+            paused,
+            interest_reserve: state.interest_reserve,
+            supplier: state.supplier,
+            market_id: state.market_id,
+            event_id: state.event_id,
+            new_players: state.new_players,
+            halted_objects: state.halted_objects,
+            queue_depth: state.queue.list.len() as u64,
+            active_listings: state.active_listings,
+            counters_since: state.counters_since,
+            economy: EconomyObserve {
+                minted: ledger.minted,
+                deposited: treasure.deposited,
+                withdrawn: treasure.withdrawn,
+            },
         };
-        serde_json::to_string(&state).unwrap()
+        serde_json::to_string(&observe).unwrap()
     }
-    pub fn get_state(pid: Vec<u64>) -> String {
-        let player = AutomataPlayer::get(&pid.try_into().unwrap()).unwrap();
-        serde_json::to_string(&player).unwrap()
+    /// The bounty pool ledger with the current pool balances as json
+    pub fn ledger() -> String {
+        ledger::to_json_string(&STATE.0.borrow().bounty_pools)
+    }
+
+    /// Admins also get the bounty pool ledger in the ledger field
+    pub fn get_state(pkey: Vec<u64>) -> String {
+        let pkey: [u64; 4] = pkey.try_into().unwrap();
+        let mut player = AutomataPlayer::get(&pkey).unwrap();
+        // show the energy regenerated so far, it is stored on the next energy change
+        player.data.regen_energy(STATE.0.borrow().queue.counter);
+        if admin::get_roles(&AutomataPlayer::pkey_to_pid(&pkey)) == 0 {
+            return serde_json::to_string(&player).unwrap();
+        }
+        let mut state = serde_json::to_value(&player).unwrap();
+        state["ledger"] = serde_json::from_str(&State::ledger()).unwrap();
+        state.to_string()
     }
 
     pub fn preempt() -> bool {
@@ -704,29 +1364,239 @@ impl State {
     pub fn store() {
         let mut state = STATE.0.borrow_mut();
         let mut v = Vec::with_capacity(state.queue.list.len() + 10);
+        v.push(STATE_TAG | STATE_VERSION);
+        v.push(Event::u64size() as u64);
         v.push(state.supplier);
-        v.push(state.bounty_pool);
+        v.push(state.bounty_pools[0]);
         v.push(state.market_id);
         v.push(state.event_id);
+        v.push(state.interest_reserve);
+        v.push(state.bounty_pools.len() as u64 - 1);
+        v.extend_from_slice(&state.bounty_pools[1..]);
+        v.push(state.withdraw_limits.window);
+        v.push(state.withdraw_limits.player_limit);
+        v.push(state.withdraw_limits.global_limit);
+        v.push(state.withdraw_limits.delay);
+        v.push(state.withdraw_window);
+        v.push(state.withdrawn);
+        v.push(state.paused);
+        ledger::check(&state.bounty_pools);
+        let ledger = ledger::get();
+        v.push(ledger.minted);
+        for token in 0..state.bounty_pools.len() {
+            let entry = ledger.pools.get(token).cloned().unwrap_or_default();
+            v.extend_from_slice(&[entry.initial, entry.deposited, entry.withdrawn]);
+        }
+        v.push(state.new_players);
+        v.push(state.halted_objects);
+        v.push(state.active_listings);
+        v.push(state.counters_since);
         state.queue.to_data(&mut v);
-        let kvpair = unsafe { &mut MERKLE_MAP };
-        kvpair.set(&[0, 0, 0, 0], v.as_slice());
-        state.queue.store();
-        let root = kvpair.merkle.root.clone();
-        zkwasm_rust_sdk::dbg!("root after store: {:?}\n", root);
+        storage::set(&[0, 0, 0, 0], v.as_slice());
+        #[cfg(target_arch = "wasm32")]
+        {
+            state.queue.store();
+            let root = unsafe { MERKLE_MAP.merkle.root.clone() };
+            zkwasm_rust_sdk::dbg!("root after store: {:?}\n", root);
+        }
     }
     pub fn initialize() {
+        config::load_params();
         let mut state = STATE.0.borrow_mut();
-        let kvpair = unsafe { &mut MERKLE_MAP };
-        let mut data = kvpair.get(&[0, 0, 0, 0]);
+        ledger::reset(&state.bounty_pools);
+        let data = storage::get(&[0, 0, 0, 0]);
         if !data.is_empty() {
+            let (version, mut data) = State::upgrade_record(data);
             let mut data = data.iter_mut();
+            let event_size = *data.next().unwrap();
+            enforce(event_size == Event::u64size() as u64, "check queue event size");
             state.supplier = *data.next().unwrap();
-            state.bounty_pool = *data.next().unwrap();
+            let treasure_pool = *data.next().unwrap();
             state.market_id = *data.next().unwrap();
             state.event_id = *data.next().unwrap();
+            state.interest_reserve = *data.next().unwrap();
+            let mut pools = State::initial_pools(treasure_pool);
+            let token_pools = *data.next().unwrap() as usize;
+            enforce(token_pools < pools.len(), "check token pools");
+            for pool in pools[1..=token_pools].iter_mut() {
+                *pool = *data.next().unwrap();
+            }
+            state.bounty_pools = pools;
+            state.withdraw_limits = WithdrawLimits {
+                window: *data.next().unwrap(),
+                player_limit: *data.next().unwrap(),
+                global_limit: *data.next().unwrap(),
+                delay: *data.next().unwrap(),
+            };
+            enforce(state.withdraw_limits.window != 0, "check withdraw window");
+            state.withdraw_window = *data.next().unwrap();
+            state.withdrawn = *data.next().unwrap();
+            state.paused = *data.next().unwrap();
+            let mut ledger = Ledger {
+                minted: *data.next().unwrap(),
+                pools: vec![PoolLedger::default(); state.bounty_pools.len()],
+            };
+            for entry in ledger.pools[..=token_pools].iter_mut() {
+                entry.initial = *data.next().unwrap();
+                entry.deposited = *data.next().unwrap();
+                entry.withdrawn = *data.next().unwrap();
+            }
+            ledger::set(ledger);
+            state.new_players = *data.next().unwrap();
+            state.halted_objects = *data.next().unwrap();
+            state.active_listings = *data.next().unwrap();
+            state.counters_since = *data.next().unwrap();
             state.queue = EventQueue::from_data(&mut data);
+            enforce(data.next().is_none(), "check global state layout");
             state.start_time_stamp = state.queue.counter;
+            if version < 7 {
+                state.counters_since = state.queue.counter;
+            }
         }
     }
+
+    /// Strips the header of a stored global state record and migrates it to the current layout.
+    /// Returns the stored version and the words after the header, starting with the event size.
+    pub fn upgrade_record(data: Vec<u64>) -> (u64, Vec<u64>) {
+        let (version, mut data) = if matches!(data.first(), Some(h) if h & STATE_TAG == STATE_TAG) {
+            (data[0] & !STATE_TAG, data[1..].to_vec())
+        } else {
+            (0, data)
+        };
+        enforce(version <= STATE_VERSION, "check global state version");
+        for migration in STATE_MIGRATIONS[version as usize..].iter() {
+            data = migration(data);
+        }
+        (version, data)
+    }
+}
+
+/* Global state record schema
+ *
+ * The record at [0, 0, 0, 0] starts with STATE_TAG | version followed by the u64 size
+ * of a queued Event, then supplier, the treasure bounty pool, market_id, event_id,
+ * interest_reserve, the number of token pools followed by the pools of tokens 1.., the
+ * withdraw limits (window, player_limit, global_limit, delay), withdraw_window, withdrawn,
+ * the pause flags, the minted total of the ledger followed by initial, deposited and withdrawn
+ * of every pool, new_players, halted_objects, active_listings, counters_since and the queue.
+ * Records written before the header existed start directly with supplier and are read
+ * as version 0. Each migration upgrades the words after the header from version i to i + 1.
+ */
+const STATE_TAG: u64 = 0xffff << 48;
+pub const STATE_VERSION: u64 = 7;
+
+/// Offsets of the sections of a state record, counted from the event size after the header.
+/// Every version appended its section after the previous ones, so an offset also holds in the
+/// records of the versions that already had that section.
+pub struct StateLayout {
+    token_pools: usize, // pools of tokens other than the treasure, the word at TOKEN_POOLS
+}
+
+impl StateLayout {
+    pub const TOKEN_POOLS: usize = 6;
+
+    pub fn new(token_pools: usize) -> Self {
+        StateLayout { token_pools }
+    }
+
+    /// Layout of the record data, which must have the number of token pools
+    pub fn of(data: &[u64]) -> Self {
+        Self::new(data[Self::TOKEN_POOLS] as usize)
+    }
+
+    pub fn withdraw_limits(&self) -> usize {
+        Self::TOKEN_POOLS + 1 + self.token_pools
+    }
+
+    pub fn paused(&self) -> usize {
+        self.withdraw_limits() + 6
+    }
+
+    pub fn ledger(&self) -> usize {
+        self.paused() + 1
+    }
+
+    pub fn counters(&self) -> usize {
+        self.ledger() + 1 + 3 * (self.token_pools + 1)
+    }
+
+    pub fn queue(&self) -> usize {
+        self.counters() + 4
+    }
+}
+
+const STATE_MIGRATIONS: [fn(Vec<u64>) -> Vec<u64>; STATE_VERSION as usize] = [
+    migrate_state_v0_to_v1,
+    migrate_state_v1_to_v2,
+    migrate_state_v2_to_v3,
+    migrate_state_v3_to_v4,
+    migrate_state_v4_to_v5,
+    migrate_state_v5_to_v6,
+    migrate_state_v6_to_v7,
+];
+
+// v1 introduced the header, events in the v0 queue were 3 words long
+fn migrate_state_v0_to_v1(data: Vec<u64>) -> Vec<u64> {
+    let mut v = Vec::with_capacity(data.len() + 1);
+    v.push(3);
+    v.extend(data);
+    v
+}
+
+// v2 added the interest reserve after event_id, existing states start with a full reserve
+fn migrate_state_v1_to_v2(mut data: Vec<u64>) -> Vec<u64> {
+    data.insert(5, CONFIG.get_interest_reserve());
+    data
+}
+
+// v3 added the pools of tokens other than the treasure after interest_reserve
+fn migrate_state_v2_to_v3(mut data: Vec<u64>) -> Vec<u64> {
+    data.insert(StateLayout::TOKEN_POOLS, 0);
+    data
+}
+
+// v4 added the withdraw limits and the global withdraw usage after the token pools
+fn migrate_state_v3_to_v4(mut data: Vec<u64>) -> Vec<u64> {
+    let at = StateLayout::of(&data).withdraw_limits();
+    let limits = WithdrawLimits::default();
+    data.splice(at..at, [limits.window, limits.player_limit, limits.global_limit, limits.delay, 0, 0]);
+    data
+}
+
+// v5 added the pause flags after the withdraw usage, nothing was paused before
+fn migrate_state_v4_to_v5(mut data: Vec<u64>) -> Vec<u64> {
+    let at = StateLayout::of(&data).paused();
+    data.insert(at, 0);
+    data
+}
+
+// v6 added the ledger after the pause flags, the pools of existing states become their initial amount
+fn migrate_state_v5_to_v6(mut data: Vec<u64>) -> Vec<u64> {
+    let layout = StateLayout::of(&data);
+    let at = layout.ledger();
+    let mut ledger = vec![0, data[2], 0, 0];
+    for pool in data[StateLayout::TOKEN_POOLS + 1..layout.withdraw_limits()].iter() {
+        ledger.extend_from_slice(&[*pool, 0, 0]);
+    }
+    data.splice(at..at, ledger);
+    data
+}
+
+// v7 added the snapshot counters after the ledger. Players and halted objects of older states can
+// not be enumerated, so they are counted from the upgrade on and initialize sets counters_since
+// to the counter of the upgrade. RestartObject compares it with the halt counter of the object,
+// so objects halted before the upgrade are not taken off halted_objects. Open listings are
+// counted once here.
+//
+// Counting the listings loads every market record, market_id - 1 reads when the upgraded build
+// first initializes the state. Market ids are never reused, so this grows with every
+// listing ever made and a deployment with a large market has to check that it fits in one block.
+fn migrate_state_v6_to_v7(mut data: Vec<u64>) -> Vec<u64> {
+    let at = StateLayout::of(&data).counters();
+    let active_listings = (1..data[3])
+        .filter_map(MarketCard::load)
+        .filter(|m| m.data.0.settleinfo != 2)
+        .count() as u64;
+    data.splice(at..at, [0, 0, active_listings, 0]);
+    data
 }
@@ -0,0 +1,47 @@
+/* Key value backend of the game state
+ *
+ * Inside the zkWasm image every record lives in the merkle map of zkwasm_rest_abi.
+ * Native builds (tests, replay and simulation tools) keep the records in memory so
+ * the game logic can run without the merkle db service.
+ */
+
+use std::slice::IterMut;
+
+/// Non panicking counterpart of StorageData::from_data, used on untrusted words.
+/// Malformed input yields ERROR_INVALID_STORAGE_DATA instead of aborting the run.
+pub trait TryFromData: Sized {
+    fn try_from_data(u64data: &mut IterMut<u64>) -> Result<Self, u32>;
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn get(key: &[u64; 4]) -> Vec<u64> {
+    let kvpair = unsafe { &mut zkwasm_rest_abi::MERKLE_MAP };
+    kvpair.get(key)
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn set(key: &[u64; 4], data: &[u64]) {
+    let kvpair = unsafe { &mut zkwasm_rest_abi::MERKLE_MAP };
+    kvpair.set(key, data);
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+lazy_static::lazy_static! {
+    static ref MEMORY: std::sync::Mutex<std::collections::BTreeMap<[u64; 4], Vec<u64>>> =
+        std::sync::Mutex::new(std::collections::BTreeMap::new());
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn get(key: &[u64; 4]) -> Vec<u64> {
+    MEMORY.lock().unwrap().get(key).cloned().unwrap_or_default()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn set(key: &[u64; 4], data: &[u64]) {
+    MEMORY.lock().unwrap().insert(*key, data.to_vec());
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn clear() {
+    MEMORY.lock().unwrap().clear();
+}
@@ -0,0 +1,67 @@
+use zkwasm_application::config::CONFIG;
+use zkwasm_application::encode;
+use zkwasm_application::error::*;
+use zkwasm_application::state::Transaction;
+
+fn round_trip(params: Vec<u64>) {
+    assert_eq!(Transaction::decode(&params).encode(), Some(params));
+}
+
+#[test]
+fn round_trip_all_commands() {
+    let nonce = 0x1234;
+    round_trip(encode::tick(0));
+    round_trip(encode::install_player(nonce));
+    round_trip(encode::install_object(nonce, 1, [0, 1, 2, 0, 1, 2, 0, 1]));
+    round_trip(encode::restart_object(nonce, 1, [2, 2, 1, 1, 0, 0, 2, 1]));
+    round_trip(encode::upgrade_object(nonce, 0, 2));
+    round_trip(encode::install_card(nonce));
+    round_trip(encode::withdraw(nonce, encode::encode_withdraw_info(100, [7; 20])));
+    round_trip(encode::deposit(nonce, [11, 12], 1000));
+    round_trip(encode::deposit_token(nonce, [11, 12], 0, 1000, 0xabcd, encode::encode_withdraw_info(0, [7; 20])));
+    round_trip(encode::bounty(nonce, 3));
+    round_trip(encode::collect_energy(nonce));
+    round_trip(encode::list_card_in_market(nonce, 3, 50));
+    round_trip(encode::bid_card(nonce, 1, 60));
+    round_trip(encode::sell_card(nonce, 3));
+    round_trip(encode::gift_energy(nonce, [11, 12], 30));
+    round_trip(encode::buy_energy_pack(nonce, 2));
+    round_trip(encode::claim_withdraw(nonce));
+    round_trip(encode::cancel_withdraw(nonce));
+    round_trip(encode::set_withdraw_limits(nonce, 17280, 1000, 100000, 720));
+    round_trip(encode::set_pause(nonce, 0b101));
+    round_trip(encode::add_admin(nonce, [11, 12], 0b110));
+    round_trip(encode::remove_admin(nonce, [11, 12]));
+    round_trip(encode::set_config(nonce, 2, 500));
+    round_trip(encode::refund_deposit(nonce, [11, 12]));
+    round_trip(encode::refund_deposit_to(nonce, [11, 12], encode::encode_withdraw_info(0, [9; 20])));
+}
+
+#[test]
+fn nonce_is_packed_above_the_opcode() {
+    let params = encode::install_card(42);
+    assert_eq!(params, vec![(42 << 16) | 5]);
+    assert_eq!(Transaction::decode(&params).nonce, 42);
+}
+
+#[test]
+fn deposit_accepts_the_four_word_layout() {
+    // PlayerConvention.deposit(nonce, pid_1, pid_2, token, amount) of the ts service
+    let params = vec![(3 << 16) | 7, 11, 12, 0, 1000];
+    assert_eq!(encode::deposit(3, [11, 12], 1000), params);
+    round_trip(params);
+    assert_eq!(encode::deposit_token(3, [11, 12], 0, 1000, 0xabcd, [0; 3]).len(), 9);
+}
+
+#[test]
+fn malformed_transactions_are_rejected() {
+    assert!(matches!(Transaction::try_decode(&[]), Err(ERROR_INVALID_PARAMS)));
+    assert!(matches!(Transaction::try_decode(&[0xff]), Err(ERROR_UNKNOWN_COMMAND)));
+    let mut params = encode::install_object(1, 0, [0; 8]);
+    params.pop();
+    assert!(matches!(Transaction::try_decode(&params), Err(ERROR_INVALID_PARAMS)));
+    let mut params = encode::withdraw(1, [100, 0, 0]);
+    params[1] = CONFIG.token_count() as u64;
+    assert!(matches!(Transaction::try_decode(&params), Err(ERROR_INVALID_PARAMS)));
+    assert_eq!(Transaction::decode(&params).encode(), None);
+}
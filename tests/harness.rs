@@ -0,0 +1,406 @@
+use zkwasm_application::admin::{ROLE_DEPOSITOR, ROLE_SUPERADMIN, ROLE_TICKER};
+use zkwasm_application::config::{Config, CONFIG, PARAM_BOUNTY_REWARD_BASE, PARAM_DEPOSIT_POLICY, PARAM_EXCLUSIVE_CARDS};
+use zkwasm_application::deposit::DEPOSIT_REJECT;
+use zkwasm_application::encode;
+use zkwasm_application::error::*;
+use zkwasm_application::harness::Harness;
+use zkwasm_application::ledger;
+use zkwasm_application::player::AutomataPlayer;
+use zkwasm_application::state::{State, PAUSE_ALL, PAUSE_MARKET};
+
+#[test]
+fn install_player_only_once() {
+    let mut h = Harness::new();
+    let player = Harness::player_key(1);
+    assert_eq!(h.install_player(&player), 0);
+    assert_eq!(h.install_player(&player), ERROR_PLAYER_ALREADY_EXIST);
+    let data = h.player(&player).unwrap();
+    assert_eq!(data.level, 1);
+    assert_eq!(data.cards.len(), 3);
+}
+
+#[test]
+fn empty_harness_has_no_admin_player() {
+    let mut h = Harness::empty();
+    assert!(h.player(&Harness::admin()).is_none());
+    assert_eq!(h.install_player(&Harness::admin()), 0);
+}
+
+#[test]
+fn installed_object_produces_resources() {
+    let mut h = Harness::new();
+    let player = Harness::player_key(1);
+    h.install_player(&player);
+    assert_eq!(h.exec(&player, |n| encode::install_object(n, 0, [0; 8])), 0);
+    let data = h.player(&player).unwrap();
+    assert_eq!(data.objects.len(), 1);
+    assert_eq!(*data.local.0.last().unwrap(), 0);
+    assert_eq!(State::queue_len(), 1);
+
+    h.ticks(120);
+    let data = h.player(&player).unwrap();
+    assert!(data.local.0[0] < 30);
+    assert!(data.local.0[2] >= 4);
+}
+
+#[test]
+fn object_modifiers_are_validated() {
+    let mut h = Harness::new();
+    let admin = Harness::admin();
+    let player = Harness::player_key(1);
+    h.install_player(&player);
+    h.deposit(&player, 5000);
+    assert_eq!(h.exec(&player, |n| encode::list_card_in_market(n, 2, 50)), 0);
+
+    assert_eq!(h.exec(&player, |n| encode::install_object(n, 0, [3; 8])), ERROR_CARD_INDEX_OUT_OF_RANGE);
+    assert_eq!(h.exec(&player, |n| encode::install_object(n, 0, [0, 0, 0, 0, 0, 0, 0, 2])), ERROR_CARD_IS_LISTED);
+    assert_eq!(h.exec(&player, |n| encode::install_object(n, 0, [0; 8])), 0);
+    // cards can be shared while exclusive_cards is off
+    assert_eq!(h.exec(&player, |n| encode::install_object(n, 1, [0, 1, 0, 1, 0, 1, 0, 1])), 0);
+
+    assert_eq!(h.exec(&player, |n| encode::restart_object(n, 0, [3; 8])), ERROR_CARD_INDEX_OUT_OF_RANGE);
+    assert_eq!(h.exec(&player, |n| encode::restart_object(n, 0, [2; 8])), ERROR_CARD_IS_LISTED);
+
+    assert_eq!(h.exec(&admin, |n| encode::set_config(n, PARAM_EXCLUSIVE_CARDS, 1)), 0);
+    assert_eq!(h.exec(&player, |n| encode::restart_object(n, 1, [1; 8])), 0);
+    assert_eq!(h.exec(&player, |n| encode::restart_object(n, 1, [0; 8])), ERROR_CARD_SHARED_BY_OBJECTS);
+    assert_eq!(h.exec(&player, |n| encode::restart_object(n, 0, [0; 8])), 0); // its own cards
+    assert_eq!(h.player(&player).unwrap().objects[1].cards, [1; 8]);
+}
+
+#[test]
+fn bad_indices_return_errors() {
+    let mut h = Harness::new();
+    let player = Harness::player_key(1);
+    h.install_player(&player);
+    h.deposit(&player, 5000);
+    assert_eq!(h.exec(&player, |n| encode::upgrade_object(n, 0, 1)), ERROR_INDEX_OUT_OF_BOUND);
+    assert_eq!(h.exec(&player, |n| encode::restart_object(n, 0, [0; 8])), ERROR_INDEX_OUT_OF_BOUND);
+    assert_eq!(h.exec(&player, |n| encode::install_object(n, 0, [0; 8])), 0);
+    assert_eq!(h.exec(&player, |n| encode::upgrade_object(n, 0, 0)), ERROR_INDEX_OUT_OF_BOUND);
+    assert_eq!(h.exec(&player, |n| encode::upgrade_object(n, 0, 4)), ERROR_INDEX_OUT_OF_BOUND);
+    assert_eq!(h.exec(&player, |n| encode::upgrade_object(n, 5, 1)), ERROR_INDEX_OUT_OF_BOUND);
+    assert_eq!(h.exec(&player, |n| encode::restart_object(n, 5, [0; 8])), ERROR_INDEX_OUT_OF_BOUND);
+    assert_eq!(h.exec(&player, |n| encode::bid_card(n, 42, 10)), ERROR_MARKET_NOT_EXIST);
+    assert_eq!(h.exec(&player, |n| encode::upgrade_object(n, 0, 1)), 0);
+    assert_eq!(h.player(&player).unwrap().objects[0].attributes, [1, 1, 0, 0]);
+}
+
+#[test]
+fn bids_above_any_balance_are_rejected() {
+    let mut h = Harness::new();
+    let (seller, bidder) = (Harness::player_key(1), Harness::player_key(5));
+    h.install_player(&seller);
+    h.install_player(&bidder);
+    assert_eq!(h.exec(&seller, |n| encode::list_card_in_market(n, 2, 50)), 0);
+    assert_eq!(h.exec(&bidder, |n| encode::bid_card(n, 1, i64::MAX as u64 + 1)), ERROR_NOT_ENOUGH_BALANCE);
+    assert_eq!(h.exec(&bidder, |n| encode::bid_card(n, 1, u64::MAX)), ERROR_NOT_ENOUGH_BALANCE);
+    assert_eq!(*h.player(&bidder).unwrap().local.0.last().unwrap(), 1000);
+}
+
+#[test]
+fn deposit_and_withdraw_move_the_pool() {
+    let mut h = Harness::new();
+    let player = Harness::player_key(1);
+    h.install_player(&player);
+    let pool = State::bounty_pool();
+    assert_eq!(h.deposit(&player, 500), 0);
+    assert_eq!(*h.player(&player).unwrap().local.0.last().unwrap(), 1500);
+    assert_eq!(State::bounty_pool(), pool + 500);
+
+    let info = encode::encode_withdraw_info(200, [1; 20]);
+    assert_eq!(h.exec(&player, |n| encode::withdraw(n, info)), 0);
+    assert_eq!(*h.player(&player).unwrap().local.0.last().unwrap(), 1300);
+    assert_eq!(State::bounty_pool(), pool + 300);
+
+    let info = encode::encode_withdraw_info(2000, [1; 20]);
+    assert_eq!(h.exec(&player, |n| encode::withdraw(n, info)), ERROR_NOT_ENOUGH_BALANCE);
+}
+
+#[test]
+fn queued_withdraw_matures_or_is_cancelled() {
+    let mut h = Harness::new();
+    let player = Harness::player_key(1);
+    h.install_player(&player);
+    let admin = Harness::admin();
+    assert_eq!(h.exec(&admin, |n| encode::set_withdraw_limits(n, 100, 500, 100000, u64::MAX)), ERROR_CONFIG_OUT_OF_BOUNDS);
+    assert_eq!(h.exec(&admin, |n| encode::set_withdraw_limits(n, u64::MAX, 500, 100000, 10)), ERROR_CONFIG_OUT_OF_BOUNDS);
+    assert_eq!(h.exec(&admin, |n| encode::set_withdraw_limits(n, 100, 500, 100000, 10)), 0);
+    let pool = State::bounty_pool();
+
+    let info = encode::encode_withdraw_info(300, [1; 20]);
+    assert_eq!(h.exec(&player, |n| encode::withdraw(n, info)), 0);
+    assert_eq!(*h.player(&player).unwrap().local.0.last().unwrap(), 700);
+    assert_eq!(State::bounty_pool(), pool - 300);
+    assert_eq!(h.exec(&player, |n| encode::withdraw(n, info)), ERROR_WITHDRAW_PENDING);
+    assert_eq!(h.exec(&player, encode::claim_withdraw), ERROR_WITHDRAW_NOT_MATURED);
+
+    assert_eq!(h.exec(&player, encode::cancel_withdraw), 0);
+    assert_eq!(*h.player(&player).unwrap().local.0.last().unwrap(), 1000);
+    assert_eq!(State::bounty_pool(), pool);
+
+    // the cancelled 300 still counts against the player limit of 500
+    assert_eq!(h.exec(&player, |n| encode::withdraw(n, info)), ERROR_WITHDRAW_LIMIT_EXCEEDED);
+    let info = encode::encode_withdraw_info(200, [1; 20]);
+    assert_eq!(h.exec(&player, |n| encode::withdraw(n, info)), 0);
+    h.ticks(10);
+    assert_eq!(h.exec(&player, encode::claim_withdraw), 0);
+    assert_eq!(h.exec(&player, encode::claim_withdraw), ERROR_NO_PENDING_WITHDRAW);
+}
+
+#[test]
+fn withdraw_limits_follow_window_changes() {
+    let mut h = Harness::new();
+    let player = Harness::player_key(1);
+    h.install_player(&player);
+    let admin = Harness::admin();
+    let info = |amount| encode::encode_withdraw_info(amount, [1; 20]);
+    assert_eq!(h.exec(&admin, |n| encode::set_withdraw_limits(n, 50, 500, 100000, 0)), 0);
+    h.ticks(60);
+    assert_eq!(h.exec(&player, |n| encode::withdraw(n, info(500))), 0);
+
+    // counter 150 is in the window 100..200, which starts after the first withdrawal
+    assert_eq!(h.exec(&admin, |n| encode::set_withdraw_limits(n, 100, 500, 100000, 0)), 0);
+    h.ticks(90);
+    assert_eq!(h.exec(&player, |n| encode::withdraw(n, info(500))), 0);
+    h.deposit(&player, 500);
+    assert_eq!(h.exec(&player, |n| encode::withdraw(n, info(1))), ERROR_WITHDRAW_LIMIT_EXCEEDED);
+}
+
+#[test]
+fn paused_classes_reject_player_commands() {
+    let mut h = Harness::new();
+    let player = Harness::player_key(1);
+    h.install_player(&player);
+    let admin = Harness::admin();
+    assert_eq!(h.exec(&admin, |n| encode::set_pause(n, PAUSE_MARKET)), 0);
+    assert_eq!(h.exec(&player, |n| encode::list_card_in_market(n, 0, 10)), ERROR_PAUSED);
+    assert_eq!(h.exec(&player, encode::install_card), 0);
+    assert_eq!(State::paused(), vec!["market"]);
+
+    assert_eq!(h.exec(&admin, |n| encode::set_pause(n, PAUSE_ALL)), 0);
+    assert_eq!(h.exec(&player, encode::install_card), ERROR_PAUSED);
+    assert_eq!(h.deposit(&player, 100), 0);
+    h.tick();
+
+    assert_eq!(h.exec(&admin, |n| encode::set_pause(n, 0)), 0);
+    assert_eq!(h.exec(&player, |n| encode::list_card_in_market(n, 0, 10)), 0);
+}
+
+#[test]
+fn admin_roles_gate_admin_commands() {
+    let mut h = Harness::new();
+    let operator = Harness::player_key(1);
+    let player = Harness::player_key(5);
+    h.install_player(&operator);
+    h.install_player(&player);
+    let operator_pid = AutomataPlayer::pkey_to_pid(&operator);
+    let pid = AutomataPlayer::pkey_to_pid(&player);
+    assert_eq!(h.exec(&operator, |n| encode::deposit(n, pid, 100)), ERROR_NOT_AUTHORIZED);
+
+    let admin = Harness::admin();
+    assert_eq!(h.exec(&admin, |n| encode::add_admin(n, operator_pid, ROLE_DEPOSITOR)), 0);
+    assert_eq!(h.exec(&operator, |n| encode::deposit(n, pid, 100)), 0);
+    assert_eq!(h.exec(&operator, |n| encode::set_pause(n, PAUSE_ALL)), ERROR_NOT_AUTHORIZED);
+    assert_eq!(h.run(&operator, &encode::tick(0)), ERROR_NOT_AUTHORIZED);
+    assert_eq!(h.exec(&operator, |n| encode::remove_admin(n, pid)), ERROR_NOT_AUTHORIZED);
+
+    assert_eq!(h.exec(&admin, |n| encode::remove_admin(n, operator_pid)), 0);
+    assert_eq!(h.exec(&operator, |n| encode::deposit(n, pid, 100)), ERROR_NOT_AUTHORIZED);
+
+    // the signer can not strip its own superadmin role
+    let admin_pid = AutomataPlayer::pkey_to_pid(&admin);
+    assert_eq!(h.exec(&admin, |n| encode::add_admin(n, admin_pid, 0)), ERROR_INVALID_PARAMS);
+    assert_eq!(h.exec(&admin, |n| encode::add_admin(n, admin_pid, ROLE_TICKER)), ERROR_INVALID_PARAMS);
+    assert_eq!(h.exec(&admin, |n| encode::remove_admin(n, admin_pid)), ERROR_INVALID_PARAMS);
+    assert_eq!(h.exec(&admin, |n| encode::add_admin(n, admin_pid, ROLE_SUPERADMIN | ROLE_TICKER)), 0);
+    h.tick();
+}
+
+#[test]
+fn config_updates_are_bounded_and_live() {
+    let mut h = Harness::new();
+    let admin = Harness::admin();
+    let player = Harness::player_key(1);
+    h.install_player(&player);
+    assert_eq!(h.exec(&admin, |n| encode::set_config(n, PARAM_BOUNTY_REWARD_BASE, 0)), ERROR_CONFIG_OUT_OF_BOUNDS);
+    assert_eq!(h.exec(&player, |n| encode::set_config(n, PARAM_BOUNTY_REWARD_BASE, 8)), ERROR_NOT_AUTHORIZED);
+    assert_eq!(h.exec(&admin, |n| encode::set_config(n, PARAM_BOUNTY_REWARD_BASE, 8)), 0);
+    assert_eq!(CONFIG.get_bounty_reward(0), 8);
+    assert!(Config::to_json_string().contains("\"bounty_reward_base\":8"));
+
+    h.reload();
+    assert_eq!(CONFIG.get_bounty_reward(0), 8);
+}
+
+#[test]
+fn token_deposits_and_withdrawals_use_their_own_pool() {
+    let mut h = Harness::with_tokens(&[Harness::test_token()]);
+    let admin = Harness::admin();
+    h.install_player(&admin);
+    let (player, other) = (Harness::player_key(1), Harness::player_key(5));
+    h.install_player(&player);
+    h.install_player(&other);
+    let pid = AutomataPlayer::pkey_to_pid(&player);
+    let treasure_pool = State::bounty_pool();
+    assert_eq!(CONFIG.token_count(), 2);
+
+    assert_eq!(h.exec(&admin, |n| encode::deposit_token(n, pid, 1, 300, 0, [0; 3])), 0);
+    assert_eq!(h.player(&player).unwrap().tokens.get(&1), Some(&300));
+    assert_eq!((State::token_pool(1), State::bounty_pool()), (300, treasure_pool));
+
+    let info = |amount| encode::encode_withdraw_info(amount, [3; 20]);
+    assert_eq!(h.exec(&player, |n| encode::withdraw_token(n, 1, info(100))), 0);
+    assert_eq!(h.player(&player).unwrap().tokens.get(&1), Some(&200));
+    assert_eq!(State::token_pool(1), 200);
+    assert_eq!(h.exec(&player, |n| encode::withdraw_token(n, 1, info(201))), ERROR_NOT_ENOUGH_POOL);
+
+    h.exec(&admin, |n| encode::deposit_token(n, AutomataPlayer::pkey_to_pid(&other), 1, 1000, 0, [0; 3]));
+    assert_eq!(h.exec(&player, |n| encode::withdraw_token(n, 1, info(201))), ERROR_NOT_ENOUGH_BALANCE);
+    assert_eq!(h.exec(&player, |n| encode::withdraw_token(n, 1, info(200))), 0);
+    assert!(h.player(&player).unwrap().tokens.is_empty());
+    assert_eq!(State::bounty_pool(), treasure_pool);
+
+    let entry = ledger::get().pools[1].clone();
+    assert_eq!((entry.deposited, entry.withdrawn), (1300, 300));
+    h.reload();
+    assert_eq!(State::token_pool(1), 1000);
+}
+
+#[test]
+fn deposit_policy_for_unknown_players() {
+    let mut h = Harness::new();
+    let admin = Harness::admin();
+    let player = Harness::player_key(1);
+    let pid = AutomataPlayer::pkey_to_pid(&player);
+    let pool = State::bounty_pool();
+    assert_eq!(h.exec(&admin, |n| encode::deposit_token(n, pid, 0, 400, 77, [0; 3])), 0);
+    assert!(h.player(&player).is_none());
+    assert_eq!(State::bounty_pool(), pool + 400);
+
+    assert_eq!(h.install_player(&player), 0);
+    assert_eq!(*h.player(&player).unwrap().local.0.last().unwrap(), 1400);
+    // the 4 word layout sent by the ts PlayerConvention
+    let nonce = h.nonce(&admin);
+    assert_eq!(h.run(&admin, &[(nonce << 16) | 7, pid[0], pid[1], 0, 100]), 0);
+    assert_eq!(*h.player(&player).unwrap().local.0.last().unwrap(), 1500);
+
+    let other = AutomataPlayer::pkey_to_pid(&Harness::player_key(5));
+    assert_eq!(h.exec(&admin, |n| encode::set_config(n, PARAM_DEPOSIT_POLICY, DEPOSIT_REJECT)), 0);
+    assert_eq!(h.exec(&admin, |n| encode::deposit(n, other, 100)), ERROR_PLAYER_NOT_EXIST);
+    assert_eq!(h.exec(&admin, |n| encode::deposit(n, pid, u64::MAX)), ERROR_BALANCE_OVERFLOW);
+}
+
+#[test]
+fn expired_pending_deposits_are_refunded() {
+    let mut h = Harness::new();
+    let admin = Harness::admin();
+    let pid = AutomataPlayer::pkey_to_pid(&Harness::player_key(1));
+    let sender = encode::encode_withdraw_info(0, [9; 20]);
+    let pool = State::bounty_pool();
+    assert_eq!(h.exec(&admin, |n| encode::deposit_token(n, pid, 0, 400, 78, sender)), 0);
+    assert_eq!(h.exec(&admin, |n| encode::refund_deposit(n, pid)), ERROR_NO_REFUNDABLE_DEPOSIT);
+
+    h.ticks(CONFIG.get_deposit_refund_timeout());
+    assert_eq!(h.exec(&admin, |n| encode::refund_deposit(n, pid)), 0);
+    assert_eq!(State::bounty_pool(), pool);
+    assert_eq!(h.exec(&admin, |n| encode::refund_deposit(n, pid)), ERROR_NO_REFUNDABLE_DEPOSIT);
+}
+
+#[test]
+fn deposits_without_sender_are_refunded_to_the_given_address() {
+    let mut h = Harness::new();
+    let admin = Harness::admin();
+    let pid = AutomataPlayer::pkey_to_pid(&Harness::player_key(1));
+    let address = encode::encode_withdraw_info(0, [9; 20]);
+    let pool = State::bounty_pool();
+    assert_eq!(h.exec(&admin, |n| encode::deposit(n, pid, 400)), 0);
+
+    h.ticks(CONFIG.get_deposit_refund_timeout());
+    assert_eq!(h.exec(&admin, |n| encode::refund_deposit(n, pid)), ERROR_NO_REFUNDABLE_DEPOSIT);
+    assert_eq!(h.exec(&admin, |n| encode::refund_deposit_to(n, pid, address)), 0);
+    assert_eq!(State::bounty_pool(), pool);
+}
+
+#[test]
+fn bounty_pool_ledger_reconciles() {
+    let mut h = Harness::new();
+    let admin = Harness::admin();
+    let player = Harness::player_key(1);
+    h.install_player(&player);
+    let initial = State::bounty_pool();
+    h.deposit(&player, 5000);
+    assert_eq!(h.exec(&player, |n| encode::withdraw(n, encode::encode_withdraw_info(1200, [7; 20]))), 0);
+    h.reload();
+    let entry = ledger::get().pools[0].clone();
+    assert_eq!((entry.initial, entry.deposited, entry.withdrawn), (initial, 5000, 1200));
+    assert_eq!(State::bounty_pool(), initial + 5000 - 1200);
+
+    let ledger: serde_json::Value = serde_json::from_str(&State::get_state(admin.to_vec())).unwrap();
+    assert_eq!(ledger["ledger"]["pools"][0]["pool"], State::bounty_pool());
+    let state: serde_json::Value = serde_json::from_str(&State::get_state(player.to_vec())).unwrap();
+    assert!(state.get("ledger").is_none());
+}
+
+#[test]
+fn snapshot_counters_survive_reload() {
+    let mut h = Harness::new();
+    let player = Harness::player_key(1);
+    h.install_player(&player);
+    h.deposit(&player, 5000);
+    assert_eq!(h.exec(&player, |n| encode::install_object(n, 0, [0; 8])), 0);
+    assert_eq!(h.exec(&player, |n| encode::list_card_in_market(n, 2, 50)), 0);
+    h.reload();
+    let snapshot: serde_json::Value = serde_json::from_str(&State::snapshot()).unwrap();
+    assert_eq!(snapshot["new_players"], 2); // the admin and the player
+    assert_eq!(snapshot["halted_objects"], 0);
+    assert_eq!(snapshot["queue_depth"], 1);
+    assert_eq!(snapshot["active_listings"], 1);
+    assert_eq!(snapshot["market_id"], State::market_id());
+    assert_eq!(snapshot["economy"]["deposited"], 5000);
+
+    h.deposit(&Harness::admin(), 50);
+    assert_eq!(h.exec(&Harness::admin(), |n| encode::bid_card(n, 1, 50)), 0);
+    let snapshot: serde_json::Value = serde_json::from_str(&State::snapshot()).unwrap();
+    assert_eq!(snapshot["active_listings"], 0);
+}
+
+#[test]
+fn snapshot_counts_halted_and_restarted_objects() {
+    let mut h = Harness::new();
+    let player = Harness::player_key(1);
+    h.install_player(&player);
+    h.deposit(&player, 5000);
+    // card 1 needs resource 2, which a new player does not have, so the object halts
+    assert_eq!(h.exec(&player, |n| encode::install_object(n, 0, [1; 8])), 0);
+    h.ticks(120);
+    let snapshot: serde_json::Value = serde_json::from_str(&State::snapshot()).unwrap();
+    assert_eq!((snapshot["halted_objects"].as_u64(), snapshot["queue_depth"].as_u64()), (Some(1), Some(0)));
+
+    assert_eq!(h.exec(&player, |n| encode::restart_object(n, 0, [0; 8])), 0);
+    h.reload();
+    let snapshot: serde_json::Value = serde_json::from_str(&State::snapshot()).unwrap();
+    assert_eq!((snapshot["halted_objects"].as_u64(), snapshot["queue_depth"].as_u64()), (Some(0), Some(1)));
+    assert_eq!(snapshot["counters_since"], 0);
+}
+
+#[test]
+fn malformed_transaction_keeps_the_nonce() {
+    let mut h = Harness::new();
+    let player = Harness::player_key(1);
+    h.install_player(&player);
+    assert_eq!(h.run(&player, &[0xff]), ERROR_UNKNOWN_COMMAND);
+    assert_eq!(h.nonce(&player), 0);
+}
+
+#[test]
+fn state_survives_reload() {
+    let mut h = Harness::new();
+    let player = Harness::player_key(1);
+    h.install_player(&player);
+    h.exec(&player, |n| encode::install_object(n, 0, [1; 8]));
+    h.ticks(7);
+    h.reload();
+    assert_eq!(State::counter(), 7);
+    assert_eq!(State::queue_len(), 1);
+}
@@ -0,0 +1,190 @@
+use zkwasm_application::card::MarketCard;
+use zkwasm_application::config::{CONFIG, COST_INCREASE_ROUND};
+use zkwasm_application::encode;
+use zkwasm_application::error::{ERROR_INTEREST_BELOW_FEE, ERROR_INTEREST_NOT_MATURED};
+use zkwasm_application::harness::{Harness, Rng};
+use zkwasm_application::player::PlayerData;
+use zkwasm_application::state::State;
+use zkwasm_rest_convention::BidObject;
+
+/* Invariants checked over random command sequences
+ *
+ * - resources and treasure of every player stay non-negative
+ * - cost_info stays in 1..=COST_INCREASE_ROUND, so pay_cost never underflows it
+ * - Deposit, Withdraw, BidCard, SellCard and interest claims conserve treasure: the sum
+ *   of player treasure plus bids escrowed in open market listings plus the interest
+ *   reserve minus bounty_pool is unchanged
+ */
+
+const PLAYERS: u64 = 4;
+const CASES: u64 = 16;
+const STEPS: u64 = 300;
+
+#[derive(PartialEq)]
+enum Step {
+    Transfer, // moves treasure without minting or burning it
+    Economy,  // may mint or burn treasure
+}
+
+fn keys() -> Vec<[u64; 4]> {
+    (0..PLAYERS).map(|i| Harness::player_key(4 * (i + 1))).collect()
+}
+
+fn treasure(data: &PlayerData) -> i64 {
+    *data.local.0.last().unwrap()
+}
+
+fn escrow() -> i64 {
+    (1..State::market_id())
+        .filter_map(MarketCard::load)
+        .filter(|m| m.data.0.settleinfo == 1)
+        .map(|m| m.data.0.get_bidder().map_or(0, |b| b.bidprice as i64))
+        .sum()
+}
+
+fn ledger(h: &Harness, keys: &[[u64; 4]]) -> i64 {
+    let treasure = keys.iter().map(|k| treasure(&h.player(k).unwrap())).sum::<i64>();
+    treasure + escrow() + State::interest_reserve() as i64 - State::bounty_pool() as i64
+}
+
+fn check_player(data: &PlayerData) {
+    for v in data.local.0.iter() {
+        assert!(*v >= 0, "negative resource in {:?}", data.local);
+    }
+    assert!(data.cost_info >= 1 && data.cost_info <= COST_INCREASE_ROUND);
+}
+
+// indices of cards that are not listed in the market, so objects never point at sold cards
+fn free_cards(data: &PlayerData) -> Vec<u8> {
+    (0..data.cards.len())
+        .filter(|i| data.cards[*i].marketid == 0)
+        .map(|i| i as u8)
+        .collect()
+}
+
+fn random_step(h: &mut Harness, rng: &mut Rng, keys: &[[u64; 4]]) -> Step {
+    let pkey = keys[rng.below(keys.len() as u64) as usize];
+    let data = h.player(&pkey).unwrap();
+    let free = free_cards(&data);
+    let mut modifiers = [0u8; 8];
+    if !free.is_empty() {
+        for m in modifiers.iter_mut() {
+            *m = free[rng.below(free.len() as u64) as usize];
+        }
+    }
+    match rng.below(12) {
+        0 => {
+            h.tick();
+            Step::Economy
+        }
+        1 if !free.is_empty() => {
+            let index = data.objects.len() as u64;
+            h.exec(&pkey, |n| encode::install_object(n, index, modifiers));
+            Step::Economy
+        }
+        2 if !data.objects.is_empty() && !free.is_empty() => {
+            let index = rng.below(data.objects.len() as u64);
+            h.exec(&pkey, |n| encode::restart_object(n, index, modifiers));
+            Step::Economy
+        }
+        3 => {
+            let nonce = h.nonce(&pkey);
+            h.submit(&pkey, &encode::install_card(nonce), &rng.rand());
+            Step::Economy
+        }
+        4 => {
+            let index = rng.below(7);
+            h.exec(&pkey, |n| encode::bounty(n, index));
+            Step::Economy
+        }
+        5 if !data.cards.is_empty() => {
+            let index = rng.below(data.cards.len() as u64);
+            let price = 1 + rng.below(500);
+            h.exec(&pkey, |n| encode::list_card_in_market(n, index, price));
+            Step::Economy
+        }
+        6 if State::market_id() > 1 => {
+            let marketid = 1 + rng.below(State::market_id() - 1);
+            let current = MarketCard::load(marketid)
+                .and_then(|m| m.data.0.get_bidder())
+                .map_or(0, |b| b.bidprice);
+            let price = current + 1 + rng.below(600);
+            h.exec(&pkey, |n| encode::bid_card(n, marketid, price));
+            Step::Transfer
+        }
+        7 if !data.cards.is_empty() => {
+            let index = rng.below(data.cards.len() as u64);
+            h.exec(&pkey, |n| encode::sell_card(n, index));
+            Step::Transfer
+        }
+        8 => {
+            h.deposit(&pkey, rng.below(1000));
+            Step::Transfer
+        }
+        9 => {
+            let info = encode::encode_withdraw_info(rng.below(2000), [1; 20]);
+            h.exec(&pkey, |n| encode::withdraw(n, info));
+            Step::Transfer
+        }
+        10 => {
+            h.exec(&pkey, |n| encode::bounty(n, 7));
+            Step::Transfer
+        }
+        _ => {
+            h.exec(&pkey, encode::collect_energy);
+            Step::Economy
+        }
+    }
+}
+
+#[test]
+fn random_sequences_keep_economy_invariants() {
+    for seed in 0..CASES {
+        let mut h = Harness::new();
+        let mut rng = Rng::new(seed);
+        let keys = keys();
+        for pkey in keys.iter() {
+            h.install_player(pkey);
+            h.deposit(pkey, 5000);
+        }
+        for step in 0..STEPS {
+            let before = ledger(&h, &keys);
+            let kind = random_step(&mut h, &mut rng, &keys);
+            for pkey in keys.iter() {
+                check_player(&h.player(pkey).unwrap());
+            }
+            if kind == Step::Transfer {
+                assert_eq!(ledger(&h, &keys), before, "treasure not conserved at seed {} step {}", seed, step);
+            }
+        }
+    }
+}
+
+#[test]
+fn interest_is_paid_from_the_reserve() {
+    let mut h = Harness::new();
+    let pkey = Harness::player_key(4);
+    h.install_player(&pkey);
+    let small = Harness::player_key(5);
+    h.install_player(&small);
+    h.deposit(&pkey, 10000000);
+    h.deposit(&small, 100000);
+    assert_eq!(h.exec(&pkey, |n| encode::bounty(n, 7)), ERROR_INTEREST_NOT_MATURED);
+
+    h.ticks(CONFIG.get_interest_holding_period());
+    let reserve = State::interest_reserve();
+    let minted = zkwasm_application::ledger::get().minted;
+
+    // about 1 treasure of interest does not cover the fee, the claim is rejected untouched
+    let before = treasure(&h.player(&small).unwrap());
+    assert_eq!(h.exec(&small, |n| encode::bounty(n, 7)), ERROR_INTEREST_BELOW_FEE);
+    assert_eq!(treasure(&h.player(&small).unwrap()), before);
+    assert_eq!(State::interest_reserve(), reserve);
+
+    let before = treasure(&h.player(&pkey).unwrap());
+    assert_eq!(h.exec(&pkey, |n| encode::bounty(n, 7)), 0);
+    let after = treasure(&h.player(&pkey).unwrap());
+    assert!(after > before);
+    assert_eq!(after - before, reserve as i64 - State::interest_reserve() as i64);
+    assert_eq!(zkwasm_application::ledger::get().minted - minted, (after - before) as u64);
+}
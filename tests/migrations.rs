@@ -0,0 +1,242 @@
+use zkwasm_application::config::CONFIG;
+use zkwasm_application::encode;
+use zkwasm_application::error::ERROR_INVALID_STORAGE_DATA;
+use zkwasm_application::harness::Harness;
+use zkwasm_application::ledger;
+use zkwasm_application::player::PlayerData;
+use zkwasm_application::state::{State, StateLayout, STATE_VERSION};
+use zkwasm_application::storage;
+use zkwasm_application::storage::TryFromData;
+
+/* Records written by older builds
+ *
+ * Each test decodes a record in the layout of an older schema version, or initializes the
+ * state from it through the native store, and checks that it loads through the migrations
+ * with the new fields at their defaults.
+ */
+
+const TAG: u64 = 0xffff << 48;
+
+// level 3, exp 7, last_check_point 9, energy 20, cost_info 2, current_cost 5,
+// redeem_info [1, 0, ..], last_interest_stamp 11
+fn baseline_head() -> Vec<u64> {
+    vec![(3 << 48) | (7 << 32) | 9, (20 << 48) | (2 << 32) | 5, 1, 11]
+}
+
+// one object on card 0, eight resources and one card
+fn baseline_tail() -> Vec<u64> {
+    let mut v = vec![1, 0x42, 0x0001_0002_0003_0004, 0];
+    v.push(8);
+    v.extend_from_slice(&[0, 1, 2, 3, 4, 5, 6, 700]);
+    v.extend_from_slice(&[1, 100, u64::from_le_bytes([0xfe, 0xfe, 4, 0, 0, 0, 0, 0]), 0]);
+    v
+}
+
+fn decode_player(mut words: Vec<u64>) -> Result<PlayerData, u32> {
+    PlayerData::try_from_data(&mut words.iter_mut())
+}
+
+#[test]
+fn baseline_player_record_is_migrated() {
+    let mut words = baseline_head();
+    words.extend(baseline_tail());
+    let data = decode_player(words).unwrap();
+    assert_eq!((data.level, data.exp, data.last_check_point), (3, 7, 9));
+    assert_eq!((data.energy, data.cost_info, data.current_cost), (20, 2, 5));
+    assert_eq!(data.redeem_info, [1, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(data.last_interest_stamp, 11);
+    assert_eq!(data.objects.len(), 1);
+    assert_eq!(data.objects[0].modifier_info, 0x42);
+    assert_eq!(data.objects[0].attributes, [4, 3, 2, 1]);
+    assert_eq!(data.local.0, vec![0, 1, 2, 3, 4, 5, 6, 700]);
+    assert_eq!(data.cards.len(), 1);
+    assert_eq!((data.cards[0].duration, data.cards[0].attributes[0]), (100, -2));
+
+    assert_eq!(data.card_rolls, 0);
+    assert_eq!(data.last_regen_stamp, 0);
+    assert_eq!(data.gift_info, 0);
+    assert!(data.tokens.is_empty());
+    assert_eq!(data.withdraw_info, 0);
+    assert!(data.pending_withdraw.is_none());
+}
+
+#[test]
+fn versioned_player_records_are_migrated() {
+    // v1 only added the header to the baseline layout
+    let mut words = vec![TAG | 1];
+    words.extend(baseline_head());
+    words.extend(baseline_tail());
+    let data = decode_player(words).unwrap();
+    assert_eq!((data.level, data.exp, data.energy), (3, 7, 20));
+    assert_eq!(data.local.0[7], 700);
+    assert_eq!(data.cards.len(), 1);
+
+    // v3 carries card_rolls and last_regen_stamp
+    let mut words = vec![TAG | 3];
+    words.extend(baseline_head());
+    words.extend_from_slice(&[2, 13]);
+    words.extend(baseline_tail());
+    let data = decode_player(words).unwrap();
+    assert_eq!((data.card_rolls, data.last_regen_stamp, data.gift_info), (2, 13, 0));
+    assert!(data.tokens.is_empty());
+    assert_eq!(data.local.0[7], 700);
+
+    // v5 carries gift_info and one token balance
+    let mut words = vec![TAG | 5];
+    words.extend(baseline_head());
+    words.extend_from_slice(&[2, 13, 17, 1, 1, 50]);
+    words.extend(baseline_tail());
+    let data = decode_player(words).unwrap();
+    assert_eq!(data.gift_info, 17);
+    assert_eq!(data.tokens.get(&1), Some(&50));
+    assert_eq!(data.withdraw_info, 0);
+    assert!(data.pending_withdraw.is_none());
+    assert_eq!(data.cards.len(), 1);
+}
+
+#[test]
+fn malformed_player_records_are_rejected() {
+    let mut words = baseline_head();
+    words.extend(baseline_tail());
+    words.pop();
+    assert!(matches!(decode_player(words), Err(ERROR_INVALID_STORAGE_DATA)));
+
+    assert!(matches!(decode_player(vec![TAG | 2, 1, 2]), Err(ERROR_INVALID_STORAGE_DATA)));
+    assert!(matches!(decode_player(vec![TAG | 99]), Err(ERROR_INVALID_STORAGE_DATA)));
+    assert!(matches!(decode_player(vec![]), Err(ERROR_INVALID_STORAGE_DATA)));
+}
+
+#[test]
+fn baseline_state_record_is_migrated() {
+    // supplier, pool, market_id, event_id and two queue words
+    let (version, words) = State::upgrade_record(vec![1000, 12345, 2, 9, 7, 8]);
+    assert_eq!(version, 0);
+    assert_eq!(&words[..5], &[3, 1000, 12345, 2, 9]);
+    assert_eq!(words[5], CONFIG.get_interest_reserve());
+}
+
+#[test]
+fn current_state_record_is_read_as_is() {
+    let words = vec![3, 1000, 12345, 2, 9, 7, 8];
+    let mut record = vec![TAG | STATE_VERSION];
+    record.extend_from_slice(&words);
+    assert_eq!(State::upgrade_record(record), (STATE_VERSION, words));
+}
+
+#[test]
+#[should_panic]
+fn newer_state_record_halts() {
+    State::upgrade_record(vec![TAG | (STATE_VERSION + 1), 3]);
+}
+
+// Runs a player with one object for 5 ticks and one open listing, then returns the queue words
+// of the stored state record
+fn running_queue(h: &mut Harness) -> Vec<u64> {
+    let player = Harness::player_key(1);
+    h.install_player(&player);
+    h.exec(&player, |n| encode::install_object(n, 0, [0; 8]));
+    h.exec(&player, |n| encode::list_card_in_market(n, 2, 50));
+    h.ticks(5);
+    State::store();
+    let record = storage::get(&[0, 0, 0, 0]);
+    record[1 + StateLayout::of(&record[1..]).queue()..].to_vec()
+}
+
+fn initialize_from(record: Vec<u64>) {
+    storage::set(&[0, 0, 0, 0], &record);
+    State::reset();
+    State::initialize();
+}
+
+#[test]
+fn baseline_state_record_is_initialized() {
+    let mut h = Harness::new();
+    let queue = running_queue(&mut h);
+    let mut record = vec![1000, 12345, 2, 9]; // supplier, pool, market_id, event_id
+    record.extend(queue);
+    initialize_from(record);
+
+    assert_eq!(State::bounty_pool(), 12345);
+    assert_eq!((State::market_id(), State::event_id()), (2, 9));
+    assert_eq!((State::counter(), State::queue_len()), (5, 1));
+    assert_eq!(State::interest_reserve(), CONFIG.get_interest_reserve());
+    assert!(State::paused().is_empty());
+    let ledger = ledger::get();
+    assert_eq!((ledger.minted, ledger.pools[0].initial, ledger.pools[0].deposited), (0, 12345, 0));
+    let snapshot: serde_json::Value = serde_json::from_str(&State::snapshot()).unwrap();
+    assert_eq!(snapshot["active_listings"], 1);
+    assert_eq!(snapshot["counters_since"], 5);
+    h.reload();
+    assert_eq!(State::bounty_pool(), 12345);
+}
+
+#[test]
+fn versioned_state_record_is_initialized() {
+    let mut h = Harness::with_tokens(&[Harness::test_token()]);
+    h.install_player(&Harness::admin());
+    let queue = running_queue(&mut h);
+    let token_pools = CONFIG.token_count().max(1) as u64 - 1;
+    // v3: event size, supplier, pool, market_id, event_id, interest_reserve, token pools
+    let mut record = vec![TAG | 3, 3, 1000, 12345, 2, 9, 77, token_pools];
+    record.extend((0..token_pools).map(|t| 100 + t));
+    record.extend(queue);
+    initialize_from(record);
+
+    assert_eq!(State::bounty_pool(), 12345);
+    assert_eq!(State::interest_reserve(), 77);
+    for t in 1..=token_pools {
+        assert_eq!(State::token_pool(t), 99 + t);
+        assert_eq!(ledger::get().pools[t as usize].initial, 99 + t);
+    }
+    assert_eq!((State::counter(), State::queue_len()), (5, 1));
+    h.reload();
+    assert_eq!(State::bounty_pool(), 12345);
+}
+
+#[test]
+#[should_panic]
+fn state_record_with_another_event_size_halts() {
+    let mut h = Harness::new();
+    let queue = running_queue(&mut h);
+    let mut record = vec![TAG | 1, 4, 1000, 12345, 2, 9];
+    record.extend(queue);
+    initialize_from(record);
+}
+
+#[test]
+#[should_panic]
+fn state_record_with_trailing_words_halts() {
+    let mut h = Harness::new();
+    let queue = running_queue(&mut h);
+    let mut record = vec![1000, 12345, 2, 9];
+    record.extend(queue);
+    record.push(1);
+    initialize_from(record);
+}
+
+#[test]
+fn objects_halted_before_the_upgrade_are_not_counted() {
+    let mut h = Harness::new();
+    let player = Harness::player_key(1);
+    h.install_player(&player);
+    h.deposit(&player, 5000);
+    // card 1 needs resource 2, which a new player does not have, so the objects halt
+    assert_eq!(h.exec(&player, |n| encode::install_object(n, 0, [1; 8])), 0);
+    h.ticks(120);
+    // store the state as a v6 record, without the snapshot counters
+    State::store();
+    let mut record = storage::get(&[0, 0, 0, 0]);
+    let at = 1 + StateLayout::of(&record[1..]).counters();
+    record.drain(at..at + 4);
+    record[0] = TAG | 6;
+    initialize_from(record);
+
+    assert_eq!(h.exec(&player, |n| encode::install_object(n, 1, [1; 8])), 0);
+    h.ticks(120);
+    let halted = || serde_json::from_str::<serde_json::Value>(&State::snapshot()).unwrap()["halted_objects"].clone();
+    assert_eq!(halted(), 1);
+    assert_eq!(h.exec(&player, |n| encode::restart_object(n, 0, [0; 8])), 0);
+    assert_eq!(halted(), 1);
+    assert_eq!(h.exec(&player, |n| encode::restart_object(n, 1, [0; 8])), 0);
+    assert_eq!(halted(), 0);
+}
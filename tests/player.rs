@@ -0,0 +1,138 @@
+use zkwasm_application::config::CONFIG;
+use zkwasm_application::encode;
+use zkwasm_application::error::*;
+use zkwasm_application::harness::Harness;
+use zkwasm_application::ledger;
+use zkwasm_application::player::{AutomataPlayer, PlayerData};
+use zkwasm_rest_convention::clear_events;
+
+/* Player progression
+ *
+ * Exercises the PlayerData rules directly, the commands that use them are covered
+ * by the harness tests.
+ */
+
+fn treasure(data: &PlayerData) -> i64 {
+    *data.local.0.last().unwrap()
+}
+
+#[test]
+fn exp_carries_over_several_levels() {
+    let _h = Harness::new();
+    let pkey = Harness::player_key(1);
+    let mut player = AutomataPlayer::new(&pkey);
+    let (energy, balance) = (player.data.energy, treasure(&player.data));
+    let exp = CONFIG.get_exp_to_level_up(1) + CONFIG.get_exp_to_level_up(2) + 5;
+    player.data.inc_exp(exp as u16);
+    assert_eq!((player.data.level, player.data.exp), (3, 5));
+    assert_eq!(player.data.level_ups.len(), 2);
+    assert_eq!(player.data.energy, energy + 2 * CONFIG.get_level_up_energy());
+    assert_eq!(treasure(&player.data), balance + 2 * CONFIG.get_level_up_treasure() as i64);
+    assert_eq!(player.data.card_rolls, 2 * CONFIG.get_level_up_card_rolls());
+    assert_eq!(ledger::get().minted, 2 * CONFIG.get_level_up_treasure());
+
+    clear_events(vec![]);
+    player.store();
+    let events = clear_events(vec![]);
+    let pid = player.player_id;
+    for level in [2, 3] {
+        let reward = [
+            pid[0],
+            pid[1],
+            level,
+            CONFIG.get_level_up_energy() as u64,
+            CONFIG.get_level_up_treasure(),
+            CONFIG.get_level_up_card_rolls() as u64,
+        ];
+        assert!(events.windows(6).any(|w| w == reward), "missing level up event for level {}", level);
+    }
+}
+
+#[test]
+fn exp_below_the_next_level_is_kept() {
+    let _h = Harness::new();
+    let mut data = PlayerData::default();
+    data.inc_exp(CONFIG.get_exp_to_level_up(1) as u16 - 1);
+    assert_eq!(data.level, 1);
+    assert!(data.level_ups.is_empty());
+    data.inc_exp(1);
+    assert_eq!((data.level, data.exp), (2, 0));
+}
+
+#[test]
+fn energy_regenerates_up_to_the_level_cap() {
+    let _h = Harness::new();
+    let period = CONFIG.get_energy_regen_period();
+    let mut data = PlayerData::default();
+    let energy = data.energy;
+    data.regen_energy(2 * period + period / 2);
+    assert_eq!(data.energy, energy + 2);
+    // the partial period is kept for the next regeneration
+    assert_eq!(data.last_regen_stamp as u64, 2 * period);
+    data.regen_energy(3 * period - 1);
+    assert_eq!(data.energy, energy + 2);
+    data.regen_energy(3 * period);
+    assert_eq!(data.energy, energy + 3);
+
+    let cap = CONFIG.get_max_energy(data.level);
+    let counter = 1000 * period;
+    data.regen_energy(counter);
+    assert_eq!(data.energy, cap);
+    assert_eq!(data.last_regen_stamp as u64, counter);
+    data.regen_energy(counter + 10 * period);
+    assert_eq!(data.energy, cap);
+
+    // energy above the cap is kept, regeneration only restarts below it
+    data.energy = cap + 5;
+    data.regen_energy(counter + 20 * period);
+    assert_eq!(data.energy, cap + 5);
+    assert!(CONFIG.get_max_energy(data.level + 1) > cap);
+}
+
+#[test]
+fn gifts_are_taxed_and_limited_per_window() {
+    let mut h = Harness::new();
+    let (sender, receiver, third) = (Harness::player_key(1), Harness::player_key(5), Harness::player_key(9));
+    let to = AutomataPlayer::pkey_to_pid(&receiver);
+    for pkey in [sender, receiver, third] {
+        h.install_player(&pkey);
+        h.deposit(&pkey, 5000);
+        assert_eq!(h.exec(&pkey, |n| encode::buy_energy_pack(n, 4)), 0);
+    }
+    let (send_limit, receive_limit) = CONFIG.get_energy_gift_limits();
+    let energy = h.player(&receiver).unwrap().energy;
+
+    assert_eq!(h.exec(&sender, |n| encode::gift_energy(n, to, 100)), 0);
+    let received = 100 - CONFIG.get_energy_gift_tax(100);
+    assert_eq!(received, 90);
+    assert_eq!(h.player(&receiver).unwrap().energy, energy + received);
+    assert_eq!(h.exec(&sender, |n| encode::gift_energy(n, to, send_limit as u64 - 100 + 1)), ERROR_ENERGY_GIFT_LIMIT_EXCEEDED);
+    assert_eq!(h.exec(&sender, |n| encode::gift_energy(n, to, send_limit as u64 - 100)), 0);
+
+    // the receiver got 180 of its 200, 30 arrive as 27 and pass the receive limit
+    assert!(2 * received + 30 - CONFIG.get_energy_gift_tax(30) > receive_limit);
+    assert_eq!(h.exec(&third, |n| encode::gift_energy(n, to, 30)), ERROR_ENERGY_GIFT_LIMIT_EXCEEDED);
+    assert_eq!(h.exec(&third, |n| encode::gift_energy(n, to, 20)), 0);
+
+    h.ticks(CONFIG.get_energy_gift_window());
+    assert_eq!(h.exec(&sender, |n| encode::gift_energy(n, to, 10)), 0);
+}
+
+#[test]
+fn energy_packs_stop_at_the_energy_cap() {
+    let mut h = Harness::new();
+    let pkey = Harness::player_key(1);
+    h.install_player(&pkey);
+    h.deposit(&pkey, 5000);
+    let (size, price) = CONFIG.get_energy_pack();
+    let data = h.player(&pkey).unwrap();
+    let packs = (0xffff - data.energy as u64) / size as u64 + 1;
+    assert_eq!(h.exec(&pkey, |n| encode::buy_energy_pack(n, packs)), ERROR_ENERGY_CAP_EXCEEDED);
+    assert_eq!(h.exec(&pkey, |n| encode::buy_energy_pack(n, u64::MAX)), ERROR_ENERGY_CAP_EXCEEDED);
+    assert_eq!(treasure(&h.player(&pkey).unwrap()), treasure(&data));
+
+    assert_eq!(h.exec(&pkey, |n| encode::buy_energy_pack(n, 2)), 0);
+    let after = h.player(&pkey).unwrap();
+    assert_eq!(after.energy, data.energy + 2 * size);
+    assert_eq!(treasure(&after), treasure(&data) - 2 * price as i64);
+}